@@ -0,0 +1,246 @@
+use std::ops::Range;
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Checksum {
+    /// CRC-8/SMBUS
+    Crc8,
+    /// CRC-16/ARC
+    Crc16,
+    /// CRC-16/CCITT-FALSE
+    Crc16Ccitt,
+    Crc32,
+    Adler32,
+    Fletcher16,
+    /// Over little endian words, a last odd byte padded with zero
+    Fletcher32,
+    /// Sum of the bytes, truncated
+    Sum8,
+    Sum16,
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// CRC of `width` bits, `reflected` ones being computed from the low bit
+fn crc(bytes: &[u8], width: u32, poly: u32, init: u32, reflected: bool) -> u32 {
+    let top = 1 << (width - 1);
+    let mask = if width == 32 {
+        u32::MAX
+    } else {
+        (1 << width) - 1
+    };
+    let mut crc = init;
+
+    for byte in bytes {
+        if reflected {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ poly
+                } else {
+                    crc >> 1
+                };
+            }
+        } else {
+            crc ^= (*byte as u32) << (width - 8);
+            for _ in 0..8 {
+                crc = if crc & top != 0 {
+                    (crc << 1) ^ poly
+                } else {
+                    crc << 1
+                };
+            }
+        }
+        crc &= mask;
+    }
+
+    crc
+}
+
+fn fletcher(words: impl Iterator<Item = u32>, modulus: u32) -> (u32, u32) {
+    words.fold((0, 0), |(a, b), word| {
+        let a = (a + word) % modulus;
+        (a, (b + a) % modulus)
+    })
+}
+
+impl Checksum {
+    pub const ALL: [Checksum; 13] = [
+        Checksum::Crc8,
+        Checksum::Crc16,
+        Checksum::Crc16Ccitt,
+        Checksum::Crc32,
+        Checksum::Adler32,
+        Checksum::Fletcher16,
+        Checksum::Fletcher32,
+        Checksum::Sum8,
+        Checksum::Sum16,
+        Checksum::Md5,
+        Checksum::Sha1,
+        Checksum::Sha256,
+        Checksum::Sha512,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Checksum::Crc8 => "crc8",
+            Checksum::Crc16 => "crc16",
+            Checksum::Crc16Ccitt => "crc16-ccitt",
+            Checksum::Crc32 => "crc32",
+            Checksum::Adler32 => "adler32",
+            Checksum::Fletcher16 => "fletcher16",
+            Checksum::Fletcher32 => "fletcher32",
+            Checksum::Sum8 => "sum8",
+            Checksum::Sum16 => "sum16",
+            Checksum::Md5 => "md5",
+            Checksum::Sha1 => "sha1",
+            Checksum::Sha256 => "sha256",
+            Checksum::Sha512 => "sha512",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Checksum::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// Whether the value is a number, which can be written in either byte
+    /// order, rather than a digest
+    pub fn is_number(&self) -> bool {
+        !matches!(
+            self,
+            Checksum::Md5 | Checksum::Sha1 | Checksum::Sha256 | Checksum::Sha512
+        )
+    }
+
+    /// Value over `bytes`, numbers in big endian
+    pub fn compute(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Checksum::Crc8 => crc(bytes, 8, 0x07, 0, false).to_be_bytes()[3..].to_vec(),
+            Checksum::Crc16 => crc(bytes, 16, 0xa001, 0, true).to_be_bytes()[2..].to_vec(),
+            Checksum::Crc16Ccitt => {
+                crc(bytes, 16, 0x1021, 0xffff, false).to_be_bytes()[2..].to_vec()
+            }
+            Checksum::Crc32 => crc32fast::hash(bytes).to_be_bytes().to_vec(),
+            Checksum::Adler32 => {
+                let (a, b) = fletcher(bytes.iter().map(|b| *b as u32), 65521);
+                // Adler-32 starts with a at 1
+                let b = (b + (bytes.len() % 65521) as u32) % 65521;
+                ((b << 16) | ((a + 1) % 65521)).to_be_bytes().to_vec()
+            }
+            Checksum::Fletcher16 => {
+                let (a, b) = fletcher(bytes.iter().map(|b| *b as u32), 255);
+                (((b << 8) | a) as u16).to_be_bytes().to_vec()
+            }
+            Checksum::Fletcher32 => {
+                let words = bytes
+                    .chunks(2)
+                    .map(|w| w[0] as u32 | (*w.get(1).unwrap_or(&0) as u32) << 8);
+                let (a, b) = fletcher(words, 65535);
+                ((b << 16) | a).to_be_bytes().to_vec()
+            }
+            Checksum::Sum8 => {
+                let sum = bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+                vec![sum]
+            }
+            Checksum::Sum16 => {
+                let sum = bytes
+                    .iter()
+                    .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+                sum.to_be_bytes().to_vec()
+            }
+            Checksum::Md5 => Md5::digest(bytes).to_vec(),
+            Checksum::Sha1 => Sha1::digest(bytes).to_vec(),
+            Checksum::Sha256 => Sha256::digest(bytes).to_vec(),
+            Checksum::Sha512 => Sha512::digest(bytes).to_vec(),
+        }
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Every checksum of a range of the data, shown in a panel
+pub struct Panel {
+    pub range: Range<usize>,
+    pub values: Vec<(Checksum, Vec<u8>)>,
+    /// Row yanked by `y`
+    pub selected: usize,
+}
+
+impl Panel {
+    pub fn new(data: &[u8], range: Range<usize>) -> Self {
+        let bytes = &data[range.clone()];
+        let values = Checksum::ALL
+            .into_iter()
+            .map(|checksum| (checksum, checksum.compute(bytes)))
+            .collect();
+
+        Self {
+            range,
+            values,
+            selected: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(input: &[u8], expected: &[(&str, &str)]) {
+        for (name, value) in expected {
+            let checksum = Checksum::from_name(name).unwrap();
+            assert_eq!(hex(&checksum.compute(input)), *value, "{name}");
+        }
+    }
+
+    #[test]
+    fn check_values() {
+        check(
+            b"123456789",
+            &[
+                ("crc8", "f4"),
+                ("crc16", "bb3d"),
+                ("crc16-ccitt", "29b1"),
+                ("crc32", "cbf43926"),
+                ("adler32", "091e01de"),
+                ("fletcher16", "1ede"),
+                ("fletcher32", "df09d509"),
+                ("sum8", "dd"),
+                ("sum16", "01dd"),
+                ("md5", "25f9e794323b453885f5181f1b624d0b"),
+                ("sha1", "f7c3bc1d808e04732adf679965ccc34ca7ae3441"),
+                (
+                    "sha256",
+                    "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+                ),
+                (
+                    "sha512",
+                    "d9e6762dd1c8eaf6d61b3c6192fc408d4d6d5f1176d0c29169bc24e71c3f274a\
+                     d27fcd5811b313d681f7e55ec02d73d499c95455b6b5bb503acf574fba8ffe85",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn odd_length() {
+        check(
+            b"abcde",
+            &[
+                ("crc16", "85b8"),
+                ("crc16-ccitt", "2fed"),
+                ("crc32", "8587d865"),
+                ("adler32", "05c801f0"),
+                ("fletcher16", "c8f0"),
+                ("fletcher32", "f04fc729"),
+            ],
+        );
+    }
+}
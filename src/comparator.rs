@@ -1,143 +1,471 @@
-use ratatui::style::Color;
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
 
-use crate::viewer::{Highlight, Viewer};
+use crate::{
+    config::Config,
+    ignore::{self, Mask},
+    panes::{Job, Panes},
+    patch::{self, Format},
+    viewer::{Highlight, Viewer},
+};
+use clap::ValueEnum;
+
+mod blocks;
 
 pub struct Comparator<'a> {
-    pub viewer_old: Viewer<'a>,
-    pub viewer_new: Viewer<'a>,
+    pub panes: Panes<'a, Vec<Hunk>, 2>,
     pub added: usize,
     pub deleted: usize,
     pub replaced: usize,
+    pub hunks: Vec<Hunk>,
+    /// Regions compared as equal whatever their content
+    pub masks: Vec<Mask>,
+    pub config: &'a Config,
+}
+
+/// Indexes of the panes
+pub const OLD: usize = 0;
+pub const NEW: usize = 1;
+
+/// Progress of a diff in permille, shared with the thread computing it
+#[derive(Default)]
+pub struct Progress {
+    done: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn set(&self, permille: usize) {
+        self.done.store(permille, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, ValueEnum)]
+pub enum Algorithm {
+    Myers,
+    Patience,
+    Lcs,
+    /// Compare byte for byte without aligning the files
+    Positional,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Myers => "myers",
+            Algorithm::Patience => "patience",
+            Algorithm::Lcs => "lcs",
+            Algorithm::Positional => "positional",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Algorithm::Myers => Algorithm::Patience,
+            Algorithm::Patience => Algorithm::Lcs,
+            Algorithm::Lcs => Algorithm::Positional,
+            Algorithm::Positional => Algorithm::Myers,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Kind {
+    Added,
+    Deleted,
+    Replaced,
+}
+
+impl Kind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kind::Added => "added",
+            Kind::Deleted => "deleted",
+            Kind::Replaced => "replaced",
+        }
+    }
+}
+
+/// A change between the two files, ranges are offsets in the original files
+#[derive(Clone, Debug)]
+pub struct Hunk {
+    pub kind: Kind,
+    pub old: Range<usize>,
+    pub new: Range<usize>,
+}
+
+fn positional(old: &[u8], new: &[u8]) -> Vec<Hunk> {
+    let length = std::cmp::min(old.len(), new.len());
+    let mut hunks = vec![];
+    let mut start = None;
+
+    for i in 0..=length {
+        match (start, i < length && old[i] != new[i]) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                hunks.push(Hunk {
+                    kind: Kind::Replaced,
+                    old: s..i,
+                    new: s..i,
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if old.len() > length {
+        hunks.push(Hunk {
+            kind: Kind::Deleted,
+            old: length..old.len(),
+            new: length..length,
+        });
+    } else if new.len() > length {
+        hunks.push(Hunk {
+            kind: Kind::Added,
+            old: length..length,
+            new: length..new.len(),
+        });
+    }
+
+    hunks
+}
+
+/// Whether a region is small enough to be diffed byte by byte
+fn small(algorithm: Algorithm, old: usize, new: usize, limit: usize) -> bool {
+    match algorithm {
+        // The LCS table holds every pair of positions
+        Algorithm::Lcs => old.saturating_mul(new) <= 1 << 20,
+        _ => std::cmp::max(old, new) <= limit,
+    }
+}
+
+fn region(
+    old: &[u8],
+    old_range: Range<usize>,
+    new: &[u8],
+    new_range: Range<usize>,
+    algorithm: Algorithm,
+    deadline: Duration,
+) -> Vec<Hunk> {
+    let kind = match (old_range.is_empty(), new_range.is_empty()) {
+        (true, true) => return vec![],
+        (true, false) => Kind::Added,
+        (false, true) => Kind::Deleted,
+        (false, false) => Kind::Replaced,
+    };
+
+    if !small(algorithm, old_range.len(), new_range.len(), REGION) {
+        return vec![Hunk {
+            kind,
+            old: old_range,
+            new: new_range,
+        }];
+    }
+
+    let algorithm = match algorithm {
+        Algorithm::Patience => similar::Algorithm::Patience,
+        Algorithm::Lcs => similar::Algorithm::Lcs,
+        _ => similar::Algorithm::Myers,
+    };
+
+    // Only the lengths of the operations are used, the indices reported by
+    // similar aren't always consistent for the side that doesn't change
+    let (mut i, mut j) = (old_range.start, new_range.start);
+    let deadline = Some(Instant::now() + deadline);
+    let diffs = similar::capture_diff_slices_deadline(
+        algorithm,
+        &old[old_range],
+        &new[new_range],
+        deadline,
+    );
+
+    let mut hunks = vec![];
+    for diff in diffs {
+        let (kind, old_len, new_len) = match diff {
+            similar::DiffOp::Equal { len, .. } => {
+                (i, j) = (i + len, j + len);
+                continue;
+            }
+            similar::DiffOp::Delete { old_len, .. } => (Kind::Deleted, old_len, 0),
+            similar::DiffOp::Insert { new_len, .. } => (Kind::Added, 0, new_len),
+            similar::DiffOp::Replace {
+                old_len, new_len, ..
+            } => (Kind::Replaced, old_len, new_len),
+        };
+
+        hunks.push(Hunk {
+            kind,
+            old: i..i + old_len,
+            new: j..j + new_len,
+        });
+        (i, j) = (i + old_len, j + new_len);
+    }
+
+    hunks
+}
+
+/// Files up to this size are diffed in one go
+const WHOLE: usize = 64 * 1024;
+
+/// Regions between anchors up to this size are diffed byte by byte
+const REGION: usize = 4 * 1024;
+
+pub fn hunks(old: &[u8], new: &[u8], algorithm: Algorithm, progress: &Progress) -> Vec<Hunk> {
+    if algorithm == Algorithm::Positional {
+        return positional(old, new);
+    }
+
+    if small(algorithm, old.len(), new.len(), WHOLE) {
+        let deadline = Duration::from_secs(1);
+        return region(old, 0..old.len(), new, 0..new.len(), algorithm, deadline);
+    }
+
+    let anchors = blocks::anchors(old, new, progress);
+    let deadline = Duration::from_millis(100);
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+
+    for (n, (a, b, length)) in anchors.iter().enumerate() {
+        if progress.cancelled() {
+            return vec![];
+        }
+        progress.set(900 + n * 100 / anchors.len());
+
+        hunks.extend(region(old, i..*a, new, j..*b, algorithm, deadline));
+        (i, j) = (a + length, b + length);
+    }
+    hunks.extend(region(
+        old,
+        i..old.len(),
+        new,
+        j..new.len(),
+        algorithm,
+        deadline,
+    ));
+
+    hunks
+}
+
+/// Number of bytes added, deleted and replaced
+pub fn count(hunks: &[Hunk]) -> (usize, usize, usize) {
+    hunks
+        .iter()
+        .fold((0, 0, 0), |(added, deleted, replaced), hunk| {
+            match hunk.kind {
+                Kind::Added => (added + hunk.new.len(), deleted, replaced),
+                Kind::Deleted => (added, deleted + hunk.old.len(), replaced),
+                Kind::Replaced => (
+                    added,
+                    deleted,
+                    replaced + std::cmp::max(hunk.old.len(), hunk.new.len()),
+                ),
+            }
+        })
 }
 
 impl<'a> Comparator<'a> {
     pub fn new(
-        data_old: &'a mut Vec<Option<u8>>,
-        data_new: &'a mut Vec<Option<u8>>,
-        file_old: &'a str,
-        file_new: &'a str,
+        data_old: &'a mut Vec<u8>,
+        data_new: &'a mut Vec<u8>,
+        file_old: Option<&'a str>,
+        file_new: Option<&'a str>,
+        algorithm: Algorithm,
+        masks: Vec<Mask>,
+        config: &'a Config,
     ) -> Self {
-        let diffs = similar::capture_diff_slices(similar::Algorithm::Myers, data_old, data_new);
+        let viewers = [
+            Viewer::new(data_old, file_old, config),
+            Viewer::new(data_new, file_new, config),
+        ];
+        let mut comparator = Self {
+            panes: Panes::new(viewers, OLD, algorithm),
+            added: 0,
+            deleted: 0,
+            replaced: 0,
+            hunks: vec![],
+            masks,
+            config,
+        };
+
+        comparator.compute();
+        comparator
+    }
 
-        let mut viewer_old = Viewer::new(data_old, Some(file_old));
-        let mut viewer_new = Viewer::new(data_new, Some(file_new));
+    /// Recomputes the differences from scratch in the background, removing the
+    /// previous alignment until the new one is ready
+    pub fn compute(&mut self) {
+        let [viewer_old, viewer_new] = &self.panes.viewers;
+        let mut old = viewer_old.data.clone();
+        let mut new = viewer_new.data.clone();
+        ignore::clear(&self.masks, &mut old);
+        ignore::clear(&self.masks, &mut new);
+        let algorithm = self.panes.algorithm;
+
+        let job = Job::spawn(1, move |progress| {
+            Some(hunks(&old, &new, algorithm, &progress[0]))
+        });
+        if self.panes.start(job) {
+            self.hunks.clear();
+            (self.added, self.deleted, self.replaced) = (0, 0, 0);
+        }
+    }
+
+    /// Recomputes the diff once a pane was edited or the algorithm changed,
+    /// aligns the buffers once it is done
+    pub fn refresh(&mut self) {
+        if self.panes.outdated() {
+            self.compute();
+        }
+        if let Some(hunks) = self.panes.poll() {
+            self.align(hunks);
+        }
+    }
 
-        let mut added = 0;
-        let mut deleted = 0;
-        let mut replaced = 0;
+    fn align(&mut self, hunks: Vec<Hunk>) {
+        let [viewer_old, viewer_new] = &mut self.panes.viewers;
+        let theme = &self.config.theme;
+        let (added, deleted, replaced) = count(&hunks);
 
         let mut offset_old = 0;
         let mut offset_new = 0;
+        let mut aligned = vec![];
+
+        for hunk in hunks.iter() {
+            let old_len = hunk.old.len();
+            let new_len = hunk.new.len();
 
-        for diff in diffs {
-            match diff {
-                similar::DiffOp::Equal { .. } => {}
-                similar::DiffOp::Delete {
-                    old_index,
-                    old_len,
-                    new_index,
-                } => {
+            let start = hunk.old.start + offset_old;
+            aligned.push(start..start + std::cmp::max(old_len, new_len));
+
+            match hunk.kind {
+                Kind::Deleted => {
                     let highlight = Highlight {
-                        start: old_index + offset_old,
-                        end: old_index + old_len + offset_old - 1,
-                        bg: Color::Red,
-                        fg: Color::White,
+                        start: hunk.old.start + offset_old,
+                        end: hunk.old.end + offset_old - 1,
+                        bg: theme.deleted.bg,
+                        fg: theme.deleted.fg,
                     };
-                    for _ in 0..old_len {
-                        viewer_new.data.insert(new_index + offset_new, None);
-                    }
+                    let gap = hunk.new.start + offset_new;
+                    viewer_new.gaps.push(gap..gap + old_len);
                     offset_new += old_len;
                     viewer_old.highlights.push(highlight);
-                    deleted += old_len;
                 }
-                similar::DiffOp::Insert {
-                    new_index,
-                    new_len,
-                    old_index,
-                } => {
+                Kind::Added => {
                     let highlight = Highlight {
-                        start: new_index + offset_new,
-                        end: new_index + new_len + offset_new - 1,
-                        bg: Color::Green,
-                        fg: Color::White,
+                        start: hunk.new.start + offset_new,
+                        end: hunk.new.end + offset_new - 1,
+                        bg: theme.added.bg,
+                        fg: theme.added.fg,
                     };
-                    for _ in 0..new_len {
-                        viewer_old.data.insert(old_index + offset_old, None);
-                    }
+                    let gap = hunk.old.start + offset_old;
+                    viewer_old.gaps.push(gap..gap + new_len);
                     offset_old += new_len;
                     viewer_new.highlights.push(highlight);
-                    added += new_len;
                 }
-                similar::DiffOp::Replace {
-                    old_index,
-                    old_len,
-                    new_index,
-                    new_len,
-                } => {
+                Kind::Replaced => {
                     let highlight = Highlight {
-                        start: old_index + offset_old,
-                        end: old_index + old_len + offset_old - 1,
-                        bg: Color::Yellow,
-                        fg: Color::Black,
+                        start: hunk.old.start + offset_old,
+                        end: hunk.old.end + offset_old - 1,
+                        bg: theme.replaced.bg,
+                        fg: theme.replaced.fg,
                     };
                     viewer_old.highlights.push(highlight);
 
                     let highlight = Highlight {
-                        start: new_index + offset_new,
-                        end: new_index + new_len + offset_new - 1,
-                        bg: Color::Yellow,
-                        fg: Color::Black,
+                        start: hunk.new.start + offset_new,
+                        end: hunk.new.end + offset_new - 1,
+                        bg: theme.replaced.bg,
+                        fg: theme.replaced.fg,
                     };
                     viewer_new.highlights.push(highlight);
 
                     if new_len > old_len {
                         let distance = new_len - old_len;
-                        for _ in 0..distance {
-                            viewer_old
-                                .data
-                                .insert(old_index + old_len + offset_old, None);
-                        }
+                        let gap = hunk.old.end + offset_old;
+                        viewer_old.gaps.push(gap..gap + distance);
                         offset_old += distance;
                     } else {
                         let distance = old_len - new_len;
-                        for _ in 0..distance {
-                            viewer_new
-                                .data
-                                .insert(new_index + new_len + offset_new, None);
-                        }
+                        let gap = hunk.new.end + offset_new;
+                        viewer_new.gaps.push(gap..gap + distance);
                         offset_new += distance;
                     }
-                    replaced += std::cmp::max(old_len, new_len);
                 }
             }
         }
 
-        Self {
-            viewer_old,
-            viewer_new,
-            added,
-            deleted,
-            replaced,
-        }
+        // Once the gaps are known
+        self.panes.dim(&self.masks, self.config.theme.ignored);
+
+        self.added = added;
+        self.deleted = deleted;
+        self.replaced = replaced;
+        self.hunks = hunks;
+        self.panes.ranges = aligned;
+        self.panes.aligned();
     }
 
-    pub fn left(&mut self) {
-        self.viewer_old.left();
-        self.viewer_new.left();
+    /// Copies the hunk under the cursor from the other pane into the focused one
+    pub fn obtain(&mut self) {
+        self.copy(self.panes.focus);
     }
 
-    pub fn right(&mut self) {
-        self.viewer_old.right();
-        self.viewer_new.right();
+    /// Copies the hunk under the cursor from the focused pane into the other one
+    pub fn put(&mut self) {
+        self.copy(1 - self.panes.focus);
     }
 
-    pub fn up(&mut self) {
-        self.viewer_old.up();
-        self.viewer_new.up();
+    /// Writes the patch turning the old file into the new one, in the format
+    /// given by the extension of `path`
+    pub fn write_patch(&mut self, path: &str) {
+        let format = Format::from_path(path);
+        let [old, new] = &self.panes.viewers;
+        let patch = patch::create(old.data, new.data, format, self.panes.algorithm);
+        let written = patch.and_then(|patch| std::fs::write(path, &patch).map(|_| patch.len()));
+
+        self.panes.focused_mut().status = Some(match written {
+            Ok(length) => format!("wrote {length} bytes of patch to {path}"),
+            Err(err) => format!("{path}: {err}"),
+        });
     }
 
-    pub fn down(&mut self) {
-        self.viewer_old.down();
-        self.viewer_new.down();
+    fn copy(&mut self, into: usize) {
+        let position = self.panes.focused().selection.start;
+        let Some(hunk) = self
+            .panes
+            .ranges
+            .iter()
+            .position(|r| r.contains(&position))
+            .map(|i| self.hunks[i].clone())
+        else {
+            self.panes.focused_mut().status = Some("no change under the cursor".to_string());
+            return;
+        };
+
+        let [old, new] = &mut self.panes.viewers;
+        let (from, to, range, target) = match into {
+            OLD => (new, old, hunk.new, hunk.old),
+            _ => (old, new, hunk.old, hunk.new),
+        };
+
+        let bytes = from.data[range].to_vec();
+        to.replace(vec![(target, bytes)]);
     }
 }
@@ -0,0 +1,168 @@
+//! Anchor matching for large files: every block of the old file is hashed and
+//! looked up with a rolling hash over the new file, so only the regions between
+//! matching blocks have to be diffed byte by byte.
+
+use std::collections::HashMap;
+
+use super::Progress;
+
+const BLOCK: usize = 32;
+const BASE: u64 = 0x100000001b3;
+
+/// How many candidate blocks are compared before giving up on a position
+const CANDIDATES: usize = 8;
+
+fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |h, b| {
+        h.wrapping_mul(BASE).wrapping_add(u64::from(*b) + 1)
+    })
+}
+
+/// Matching regions of the two files as `(old, new, length)`, in increasing order
+pub fn anchors(old: &[u8], new: &[u8], progress: &Progress) -> Vec<(usize, usize, usize)> {
+    let mut anchors = vec![];
+
+    if new.len() < BLOCK {
+        return anchors;
+    }
+
+    let mut blocks: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, block) in old.chunks_exact(BLOCK).enumerate() {
+        blocks.entry(hash(block)).or_default().push(i * BLOCK);
+    }
+    progress.set(100);
+
+    // Weight of the byte leaving the window
+    let power = (1..BLOCK).fold(1u64, |p, _| p.wrapping_mul(BASE));
+
+    let (mut old_end, mut new_end) = (0, 0);
+    let mut h = hash(&new[..BLOCK]);
+    let mut j = 0;
+    let mut report = 0;
+
+    while j + BLOCK <= new.len() {
+        if j >= report {
+            if progress.cancelled() {
+                return vec![];
+            }
+            progress.set(100 + j * 800 / new.len());
+            report = j + (1 << 16);
+        }
+
+        // Where the block would be if nothing moved since the last anchor, tried
+        // first as repetitive data matches many blocks, then the candidates
+        // closest to it
+        let expected = old_end + (j - new_end);
+        let diagonal = (expected + BLOCK <= old.len()).then_some(expected);
+        let candidates = blocks.get(&h).map_or(&[][..], |c| &c[..]);
+        let k = candidates.partition_point(|i| *i < expected);
+        let mut after = candidates[k..].iter().take(CANDIDATES).peekable();
+        let mut before = candidates[..k]
+            .iter()
+            .rev()
+            .take_while(|i| **i >= old_end)
+            .take(CANDIDATES)
+            .peekable();
+        let closest = std::iter::from_fn(|| match (after.peek(), before.peek()) {
+            (Some(a), Some(b)) if **a - expected > expected - **b => before.next(),
+            (Some(_), _) => after.next(),
+            (None, _) => before.next(),
+        });
+
+        let found = diagonal
+            .into_iter()
+            .chain(closest.copied())
+            .find(|i| old[*i..*i + BLOCK] == new[j..j + BLOCK]);
+
+        let Some(mut i) = found else {
+            if j + BLOCK < new.len() {
+                let out = u64::from(new[j]) + 1;
+                let into = u64::from(new[j + BLOCK]) + 1;
+                h = h
+                    .wrapping_sub(out.wrapping_mul(power))
+                    .wrapping_mul(BASE)
+                    .wrapping_add(into);
+            }
+            j += 1;
+            continue;
+        };
+
+        let mut length = BLOCK;
+        while i > old_end && j > new_end && old[i - 1] == new[j - 1] {
+            i -= 1;
+            j -= 1;
+            length += 1;
+        }
+        while i + length < old.len() && j + length < new.len() && old[i + length] == new[j + length]
+        {
+            length += 1;
+        }
+
+        anchors.push((i, j, length));
+        (old_end, new_end) = (i + length, j + length);
+
+        j = new_end;
+        if j + BLOCK <= new.len() {
+            h = hash(&new[j..j + BLOCK]);
+        }
+    }
+
+    anchors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes without repeated blocks
+    fn noise(length: usize, seed: u64) -> Vec<u8> {
+        let mut x = seed | 1;
+        (0..length)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn anchors(old: &[u8], new: &[u8]) -> Vec<(usize, usize, usize)> {
+        super::anchors(old, new, &Progress::default())
+    }
+
+    #[test]
+    fn identical() {
+        let data = noise(1000, 1);
+        assert_eq!(anchors(&data, &data), [(0, 0, 1000)]);
+        assert_eq!(anchors(&data[..10], &data[..10]), []);
+    }
+
+    #[test]
+    fn insertion() {
+        let old = noise(1000, 2);
+        let new = [&old[..300], b"12345", &old[300..]].concat();
+        assert_eq!(anchors(&old, &new), [(0, 0, 300), (300, 305, 700)]);
+    }
+
+    #[test]
+    fn unaligned_deletion() {
+        // The rest of the file is no longer at the offset of a block of the old one
+        let old = noise(1000, 3);
+        assert_eq!(anchors(&old, &old[7..]), [(7, 0, 993)]);
+    }
+
+    #[test]
+    fn repetitive() {
+        // Any block of zeros matches, the one after the insertion has to be kept
+        let mut old = noise(1000, 4);
+        old.resize(100_000, 0);
+        let mut new = [&old[..500], b"12345", &old[500..]].concat();
+        new[50_000] = 7;
+
+        assert_eq!(
+            anchors(&old, &new),
+            [(0, 0, 500), (500, 505, 49_495), (49_996, 50_001, 50_004)]
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use ratatui::style::Color;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::keymap::{Action, Bindings, Keymap, Sequence};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+fn parse_color(s: &str) -> Result<Color, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("invalid color {s:?}, expected a name, an index or #rrggbb"))
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).map_err(de::Error::custom)
+}
+
+/// Background and foreground colors, written `"bg"` or `"bg/fg"`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Pair {
+    pub bg: Color,
+    pub fg: Color,
+}
+
+impl TryFrom<String> for Pair {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let (bg, fg) = s.split_once('/').unwrap_or((&s, "white"));
+        Ok(Pair {
+            bg: parse_color(bg)?,
+            fg: parse_color(fg)?,
+        })
+    }
+}
+
+/// Foreground colors of the bytes by class, in the hex and ASCII panes
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bytes {
+    #[serde(deserialize_with = "color")]
+    pub null: Color,
+    #[serde(deserialize_with = "color")]
+    pub printable: Color,
+    #[serde(deserialize_with = "color")]
+    pub whitespace: Color,
+    #[serde(deserialize_with = "color")]
+    pub control: Color,
+    /// Bytes with the high bit set, except 0xFF
+    #[serde(deserialize_with = "color")]
+    pub high: Color,
+    #[serde(deserialize_with = "color")]
+    pub ff: Color,
+}
+
+impl Bytes {
+    pub fn color(&self, byte: u8) -> Color {
+        match byte {
+            0x00 => self.null,
+            b'\t' | b'\n' | 0x0b | 0x0c | b'\r' | b' ' => self.whitespace,
+            0x21..=0x7e => self.printable,
+            0x01..=0x1f | 0x7f => self.control,
+            0x80..=0xfe => self.high,
+            0xff => self.ff,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Theme {
+    pub selection: Pair,
+    pub added: Pair,
+    pub deleted: Pair,
+    pub replaced: Pair,
+    /// Regions changed differently by both sides of a three-way comparison
+    pub conflict: Pair,
+    /// Regions left out of comparisons
+    pub ignored: Pair,
+    /// Changes of a patch before it is applied
+    pub preview: Pair,
+    /// Border of the focused pane
+    #[serde(deserialize_with = "color")]
+    pub focus: Color,
+    /// Colors of the highlights, used in turn
+    pub highlights: Vec<Pair>,
+    pub bytes: Bytes,
+}
+
+const DARK: &str = r##"
+selection = "darkgray/white"
+added = "green/white"
+deleted = "red/white"
+replaced = "yellow/black"
+conflict = "red/white"
+ignored = "reset/darkgray"
+preview = "magenta/white"
+focus = "cyan"
+highlights = ["red/white", "green/white", "yellow/black", "blue/white"]
+
+[bytes]
+null = "darkgray"
+printable = "cyan"
+whitespace = "green"
+control = "magenta"
+high = "yellow"
+ff = "red"
+"##;
+
+const LIGHT: &str = r##"
+selection = "gray/black"
+added = "lightgreen/black"
+deleted = "lightred/black"
+replaced = "lightyellow/black"
+conflict = "lightred/black"
+ignored = "reset/gray"
+preview = "lightmagenta/black"
+focus = "blue"
+highlights = ["lightred/black", "lightgreen/black", "lightyellow/black", "lightblue/black"]
+
+[bytes]
+null = "gray"
+printable = "blue"
+whitespace = "green"
+control = "magenta"
+high = "#875f00"
+ff = "red"
+"##;
+
+const HIGH_CONTRAST: &str = r##"
+selection = "white/black"
+added = "lightgreen/black"
+deleted = "lightred/black"
+replaced = "lightyellow/black"
+conflict = "lightmagenta/black"
+ignored = "reset/darkgray"
+preview = "lightcyan/black"
+focus = "lightyellow"
+highlights = ["lightred/black", "lightgreen/black", "lightyellow/black", "lightblue/black"]
+
+[bytes]
+null = "darkgray"
+printable = "white"
+whitespace = "lightgreen"
+control = "lightmagenta"
+high = "lightyellow"
+ff = "lightred"
+"##;
+
+/// Built-in themes, written like the ones of the config file
+const THEMES: &[(&str, &str)] = &[
+    ("dark", DARK),
+    ("light", LIGHT),
+    ("high-contrast", HIGH_CONTRAST),
+];
+
+/// Overwrites the values of `base` with the ones of `overrides`, table by table
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Values of the theme `name`, filled in by the theme it is based on
+fn resolve(
+    name: &str,
+    themes: &HashMap<String, toml::Table>,
+    seen: &mut Vec<String>,
+) -> Result<toml::Table, String> {
+    if seen.iter().any(|n| n == name) {
+        return Err(format!("theme {name:?} is based on itself"));
+    }
+    seen.push(name.to_string());
+
+    if let Some((_, text)) = THEMES.iter().find(|(n, _)| *n == name) {
+        return Ok(text.parse().unwrap());
+    }
+
+    let Some(theme) = themes.get(name) else {
+        let names: Vec<_> = THEMES
+            .iter()
+            .map(|(n, _)| *n)
+            .chain(themes.keys().map(|n| n.as_str()))
+            .collect();
+        return Err(format!(
+            "unknown theme {name:?}, expected one of {}",
+            names.join(", ")
+        ));
+    };
+
+    let mut theme = theme.clone();
+    let base = match theme.remove("base") {
+        Some(toml::Value::String(base)) => base,
+        Some(_) => return Err(format!("themes.{name}.base must be the name of a theme")),
+        None => "dark".to_string(),
+    };
+
+    let mut table = resolve(&base, themes, seen)?;
+    merge(&mut table, theme);
+    Ok(table)
+}
+
+impl Theme {
+    /// The built-in theme or the one from `themes` called `name`, with the
+    /// `colors` overrides on top
+    fn new(
+        name: &str,
+        themes: &HashMap<String, toml::Table>,
+        colors: toml::Table,
+    ) -> Result<Self, String> {
+        let mut table = resolve(name, themes, &mut vec![])?;
+        merge(&mut table, colors);
+
+        let theme: Theme = toml::Value::Table(table).try_into().map_err(|err| {
+            format!(
+                "theme {name:?}: {}",
+                err.to_string().trim_end().replace('\n', " ")
+            )
+        })?;
+
+        if theme.highlights.is_empty() {
+            return Err(format!(
+                "theme {name:?}: highlights needs at least one color"
+            ));
+        }
+
+        Ok(theme)
+    }
+}
+
+/// Side panels shown when the terminal is wide enough
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Panels {
+    pub info: bool,
+    pub difference: bool,
+    pub inspector: bool,
+    pub hunks: bool,
+    /// Entropy of the blocks of the file and histogram of the selection
+    pub entropy: bool,
+}
+
+impl Default for Panels {
+    fn default() -> Self {
+        Self {
+            info: true,
+            difference: true,
+            inspector: true,
+            hunks: true,
+            entropy: true,
+        }
+    }
+}
+
+/// Bindings added to the default ones, per mode
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Keys {
+    normal: HashMap<Sequence, Action>,
+    visual: HashMap<Sequence, Action>,
+    insert: HashMap<Sequence, Action>,
+    compare: HashMap<Sequence, Action>,
+}
+
+/// Layout of the config file
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    width: usize,
+    word: usize,
+    scrolloff: usize,
+    endian: Endian,
+    panels: Panels,
+    /// Name of a built-in theme or of one of `themes`
+    theme: String,
+    themes: HashMap<String, toml::Table>,
+    /// Overrides of the colors of the theme
+    colors: toml::Table,
+    keys: Keys,
+}
+
+impl Default for File {
+    fn default() -> Self {
+        Self {
+            width: 16,
+            word: 4,
+            scrolloff: 2,
+            endian: Endian::Big,
+            panels: Panels::default(),
+            theme: "dark".to_string(),
+            themes: HashMap::new(),
+            colors: toml::Table::new(),
+            keys: Keys::default(),
+        }
+    }
+}
+
+pub struct Config {
+    /// Bytes per row
+    pub width: usize,
+    /// Bytes jumped over by `w` and `b`
+    pub word: usize,
+    /// Rows kept between the cursor and the edges of the view
+    pub scrolloff: usize,
+    pub endian: Endian,
+    pub panels: Panels,
+    pub theme: Theme,
+    pub keys: Keymap,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(File::default()).unwrap()
+    }
+}
+
+fn extend(bindings: &mut Bindings, keys: HashMap<Sequence, Action>) {
+    bindings.extend(
+        keys.into_iter()
+            .map(|(sequence, action)| (sequence.0, action)),
+    );
+}
+
+/// `$XDG_CONFIG_HOME/lazyhex/config.toml`, or in `~/.config` by default
+pub fn path() -> Option<PathBuf> {
+    let directory = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(directory.join("lazyhex").join("config.toml"))
+}
+
+impl Config {
+    fn new(file: File) -> Result<Self, String> {
+        if !(1..=64).contains(&file.width) {
+            return Err(format!(
+                "width must be between 1 and 64, got {}",
+                file.width
+            ));
+        }
+        if ![2, 4, 8].contains(&file.word) {
+            return Err(format!("word must be 2, 4 or 8, got {}", file.word));
+        }
+        if let Some(name) = file
+            .themes
+            .keys()
+            .find(|name| THEMES.iter().any(|(n, _)| n == name))
+        {
+            return Err(format!("themes.{name} has the name of a built-in theme"));
+        }
+
+        let theme = Theme::new(&file.theme, &file.themes, file.colors)?;
+
+        let mut keys = Keymap::default();
+        extend(&mut keys.normal, file.keys.normal);
+        extend(&mut keys.visual, file.keys.visual);
+        extend(&mut keys.insert, file.keys.insert);
+        extend(&mut keys.compare, file.keys.compare);
+
+        Ok(Self {
+            width: file.width,
+            word: file.word,
+            scrolloff: file.scrolloff,
+            endian: file.endian,
+            panels: file.panels,
+            theme,
+            keys,
+        })
+    }
+
+    /// Reads the given config file, or the default one if it exists, `theme`
+    /// replacing the one it selects
+    pub fn load(path: Option<&str>, theme: Option<&str>) -> Result<Self, String> {
+        let path = match path {
+            Some(path) => Some(PathBuf::from(path)),
+            None => self::path().filter(|path| path.exists()),
+        };
+
+        let mut file = match &path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|err| format!("{}: {err}", path.display()))?;
+                toml::from_str(&text).map_err(|err| format!("{}: {err}", path.display()))?
+            }
+            None => File::default(),
+        };
+
+        if let Some(theme) = theme {
+            file.theme = theme.to_string();
+        }
+
+        Self::new(file).map_err(|err| match &path {
+            Some(path) => format!("{}: {err}", path.display()),
+            None => err,
+        })
+    }
+}
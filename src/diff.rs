@@ -0,0 +1,130 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::{
+    comparator::{count, hunks, Algorithm, Hunk, Progress},
+    ignore,
+};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Output {
+    Text,
+    Json,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// Original file
+    old: String,
+    /// Modified file
+    new: String,
+    #[arg(short, long, value_enum, default_value_t = Output::Text)]
+    format: Output,
+    #[arg(short, long, value_enum, default_value_t = Algorithm::Myers)]
+    algorithm: Algorithm,
+    #[command(flatten)]
+    ignore: crate::ignore::Options,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn text(old: &[u8], new: &[u8], hunks: &[Hunk]) -> Vec<String> {
+    let mut lines = vec![];
+
+    for hunk in hunks {
+        lines.push(format!(
+            "@@ 0x{:08x},{} 0x{:08x},{} @@ {}",
+            hunk.old.start,
+            hunk.old.len(),
+            hunk.new.start,
+            hunk.new.len(),
+            hunk.kind.name()
+        ));
+        if !hunk.old.is_empty() {
+            lines.push(format!("- {}", hex(&old[hunk.old.clone()])));
+        }
+        if !hunk.new.is_empty() {
+            lines.push(format!("+ {}", hex(&new[hunk.new.clone()])));
+        }
+    }
+
+    let (added, deleted, replaced) = count(hunks);
+    lines.push(format!(
+        "{} hunks, {added} added, {deleted} deleted, {replaced} replaced",
+        hunks.len()
+    ));
+
+    lines
+}
+
+fn json(options: &Options, old: &[u8], new: &[u8], hunks: &[Hunk]) -> Vec<String> {
+    let (added, deleted, replaced) = count(hunks);
+
+    let hunks: Vec<_> = hunks
+        .iter()
+        .map(|hunk| {
+            json!({
+                "kind": hunk.kind.name(),
+                "old_offset": hunk.old.start,
+                "old_length": hunk.old.len(),
+                "new_offset": hunk.new.start,
+                "new_length": hunk.new.len(),
+                "old": hex(&old[hunk.old.clone()]).replace(' ', ""),
+                "new": hex(&new[hunk.new.clone()]).replace(' ', ""),
+            })
+        })
+        .collect();
+
+    let value = json!({
+        "old": options.old,
+        "new": options.new,
+        "added": added,
+        "deleted": deleted,
+        "replaced": replaced,
+        "hunks": hunks,
+    });
+
+    vec![serde_json::to_string_pretty(&value).unwrap()]
+}
+
+fn load(path: &str) -> std::io::Result<Vec<u8>> {
+    crate::load(path).map_err(crate::context(path))
+}
+
+/// Prints the differences between two files, returns whether they differ
+pub fn diff(options: Options) -> std::io::Result<bool> {
+    let old = load(&options.old)?;
+    let new = load(&options.new)?;
+
+    let masks = options.ignore.masks()?;
+    let (mut cleared_old, mut cleared_new) = (old.clone(), new.clone());
+    ignore::clear(&masks, &mut cleared_old);
+    ignore::clear(&masks, &mut cleared_new);
+
+    let hunks = hunks(
+        &cleared_old,
+        &cleared_new,
+        options.algorithm,
+        &Progress::default(),
+    );
+
+    let lines = match options.format {
+        Output::Text => text(&old, &new, &hunks),
+        Output::Json => json(&options, &old, &new, &hunks),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        writeln!(stdout, "{line}")?;
+    }
+
+    Ok(!hunks.is_empty())
+}
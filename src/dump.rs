@@ -0,0 +1,195 @@
+use std::io::{IsTerminal, Write};
+
+use clap::ValueEnum;
+use crossterm::style::Stylize;
+use serde_json::json;
+
+use crate::ui::{offset, printable};
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Output {
+    /// Same layout as the viewer: offset, hex and ASCII
+    Plain,
+    /// Compatible with `xxd -r`
+    Xxd,
+    /// C array declaration
+    C,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum When {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Options {
+    /// File to dump, `-` reads from stdin
+    file: String,
+    /// First byte to dump
+    #[arg(short, long, default_value = "0", value_parser = crate::number)]
+    offset: usize,
+    /// Number of bytes to dump, defaults to the rest of the file
+    #[arg(short = 'n', long, value_parser = crate::number)]
+    length: Option<usize>,
+    /// Bytes per row
+    #[arg(short, long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
+    #[arg(long, value_enum, default_value_t = When::Auto)]
+    color: When,
+    #[arg(short, long, value_enum, default_value_t = Output::Plain)]
+    format: Output,
+}
+
+fn paint(text: String, byte: u8, color: bool) -> String {
+    if !color {
+        text
+    } else if byte == 0 {
+        text.dark_grey().to_string()
+    } else if printable(byte) != '.' {
+        text.green().to_string()
+    } else {
+        text
+    }
+}
+
+fn plain(data: &[u8], start: usize, width: usize, color: bool) -> Vec<String> {
+    data.chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let index = offset(start + i * width);
+            let index = if color {
+                index.dark_grey().to_string()
+            } else {
+                index
+            };
+
+            let mut hex = String::new();
+            for j in 0..width {
+                if j == width / 2 && j != 0 {
+                    hex.push(' ');
+                }
+                match chunk.get(j) {
+                    Some(b) => hex.push_str(&paint(format!("{b:02x}"), *b, color)),
+                    None => hex.push_str("  "),
+                }
+                hex.push(' ');
+            }
+
+            let ascii: String = chunk
+                .iter()
+                .map(|b| paint(printable(*b).to_string(), *b, color))
+                .collect();
+
+            format!("{index}  {hex} {ascii}")
+        })
+        .collect()
+}
+
+fn xxd(data: &[u8], start: usize, width: usize, color: bool) -> Vec<String> {
+    let size = width * 2 + width.div_ceil(2) - 1;
+
+    data.chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let groups: Vec<_> = chunk
+                .chunks(2)
+                .map(|group| {
+                    group
+                        .iter()
+                        .map(|b| paint(format!("{b:02x}"), *b, color))
+                        .collect::<String>()
+                })
+                .collect();
+            let length = chunk.len() * 2 + chunk.len().div_ceil(2) - 1;
+            let padding = " ".repeat(size - length);
+
+            let ascii: String = chunk
+                .iter()
+                .map(|b| match b {
+                    0x20..=0x7e => paint((*b as char).to_string(), *b, color),
+                    _ => paint(".".to_string(), *b, color),
+                })
+                .collect();
+
+            format!(
+                "{:08x}: {}{padding}  {ascii}",
+                start + i * width,
+                groups.join(" ")
+            )
+        })
+        .collect()
+}
+
+fn c(data: &[u8], width: usize) -> Vec<String> {
+    let rows = data.chunks(width).map(|chunk| {
+        let bytes: Vec<_> = chunk.iter().map(|b| format!("0x{b:02x}")).collect();
+        format!("  {}", bytes.join(", "))
+    });
+
+    ["unsigned char data[] = {".to_string()]
+        .into_iter()
+        .chain([rows.collect::<Vec<_>>().join(",\n")])
+        .chain([
+            "};".to_string(),
+            format!("unsigned int data_len = {};", data.len()),
+        ])
+        .collect()
+}
+
+fn json(file: &str, data: &[u8], start: usize, width: usize) -> Vec<String> {
+    let rows: Vec<_> = data
+        .chunks(width)
+        .enumerate()
+        .map(|(i, chunk)| {
+            json!({
+                "offset": start + i * width,
+                "hex": chunk.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+                "ascii": chunk.iter().map(|b| printable(*b)).collect::<String>(),
+            })
+        })
+        .collect();
+
+    let value = json!({
+        "file": file,
+        "offset": start,
+        "length": data.len(),
+        "rows": rows,
+    });
+
+    vec![serde_json::to_string_pretty(&value).unwrap()]
+}
+
+pub fn dump(options: Options) -> std::io::Result<()> {
+    let data = crate::load(&options.file).map_err(crate::context(&options.file))?;
+
+    let start = std::cmp::min(options.offset, data.len());
+    let end = match options.length {
+        Some(length) => std::cmp::min(start.saturating_add(length), data.len()),
+        None => data.len(),
+    };
+    let data = &data[start..end];
+    let width = options.width as usize;
+
+    let color = match options.color {
+        When::Auto => std::io::stdout().is_terminal(),
+        When::Always => true,
+        When::Never => false,
+    };
+
+    let lines = match options.format {
+        Output::Plain => plain(data, start, width, color),
+        Output::Xxd => xxd(data, start, width, color),
+        Output::C => c(data, width),
+        Output::Json => json(&options.file, data, start, width),
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    for line in lines {
+        writeln!(stdout, "{line}")?;
+    }
+
+    Ok(())
+}
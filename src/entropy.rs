@@ -0,0 +1,104 @@
+use std::ops::Range;
+
+use crate::panes::Job;
+
+/// Number of times each byte value appears
+pub fn histogram(bytes: &[u8]) -> Vec<u64> {
+    let mut counts = vec![0; 256];
+    for byte in bytes {
+        counts[*byte as usize] += 1;
+    }
+    counts
+}
+
+/// Shannon entropy in bits per byte, from 0 for a single value repeated to 8
+/// for random data
+pub fn entropy(bytes: &[u8]) -> f64 {
+    let length = bytes.len() as f64;
+    histogram(bytes)
+        .into_iter()
+        .filter(|count| *count > 0)
+        .map(|count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Entropy of the blocks of the data and histogram of the selection,
+/// computed in the background when the data, the number of blocks or the
+/// selection change and shown as they were until then
+#[derive(Default)]
+pub struct Entropy {
+    /// Bytes in each block
+    pub size: usize,
+    /// Entropy of each block in hundredths of bits per byte
+    pub blocks: Vec<u64>,
+    pub histogram: Vec<u64>,
+    /// Range of the data counted in the histogram
+    pub range: Range<usize>,
+    blocks_key: Option<(usize, usize)>,
+    histogram_key: Option<(usize, Range<usize>)>,
+    blocks_job: Option<Job<(usize, Vec<u64>)>>,
+    histogram_job: Option<Job<(Range<usize>, Vec<u64>)>>,
+}
+
+impl Entropy {
+    /// Splits the data in at most `count` blocks, and counts the bytes of `range`
+    pub fn update(&mut self, data: &[u8], version: usize, count: usize, range: Range<usize>) {
+        if count == 0 {
+            return;
+        }
+
+        if self.blocks_key != Some((version, count)) {
+            self.blocks_key = Some((version, count));
+            if let Some(job) = self.blocks_job.take() {
+                job.cancel();
+            }
+
+            let size = data.len().div_ceil(count);
+            let data = data.to_vec();
+            self.blocks_job = Some(Job::spawn(1, move |progress| {
+                let mut blocks = vec![];
+                for block in data.chunks(size) {
+                    if progress[0].cancelled() {
+                        return None;
+                    }
+                    blocks.push((entropy(block) * 100.0).round() as u64);
+                }
+                Some((size, blocks))
+            }));
+        }
+
+        let key = Some((version, range.clone()));
+        if self.histogram_key != key {
+            self.histogram_key = key;
+            if let Some(job) = self.histogram_job.take() {
+                job.cancel();
+            }
+
+            let bytes = data[range.clone()].to_vec();
+            self.histogram_job = Some(Job::spawn(1, move |_| Some((range, histogram(&bytes)))));
+        }
+
+        if let Some((size, blocks)) = self.blocks_job.as_ref().and_then(|job| job.poll()) {
+            self.blocks_job = None;
+            (self.size, self.blocks) = (size, blocks);
+        }
+        if let Some((range, histogram)) = self.histogram_job.as_ref().and_then(|job| job.poll()) {
+            self.histogram_job = None;
+            (self.range, self.histogram) = (range, histogram);
+        }
+    }
+
+    /// Whether blocks or a histogram are being computed
+    pub fn busy(&self) -> bool {
+        self.blocks_job.is_some() || self.histogram_job.is_some()
+    }
+
+    /// Mean entropy of the blocks in bits per byte
+    pub fn mean(&self) -> f64 {
+        let sum: u64 = self.blocks.iter().sum();
+        sum as f64 / self.blocks.len().max(1) as f64 / 100.0
+    }
+}
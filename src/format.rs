@@ -0,0 +1,88 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Hex,
+    SpacedHex,
+    CArray,
+    Rust,
+    Python,
+    Base64,
+    Escaped,
+}
+
+impl Format {
+    pub const ALL: [Format; 7] = [
+        Format::Hex,
+        Format::SpacedHex,
+        Format::CArray,
+        Format::Rust,
+        Format::Python,
+        Format::Base64,
+        Format::Escaped,
+    ];
+
+    pub fn key(&self) -> char {
+        match self {
+            Format::Hex => 'x',
+            Format::SpacedHex => 's',
+            Format::CArray => 'c',
+            Format::Rust => 'r',
+            Format::Python => 'p',
+            Format::Base64 => 'b',
+            Format::Escaped => 'e',
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Hex => "hex",
+            Format::SpacedHex => "spaced hex",
+            Format::CArray => "C array",
+            Format::Rust => "Rust array",
+            Format::Python => "Python bytes",
+            Format::Base64 => "base64",
+            Format::Escaped => "escaped string",
+        }
+    }
+
+    pub fn from_key(key: char) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.key() == key)
+    }
+
+    pub fn format(&self, bytes: &[u8]) -> String {
+        match self {
+            Format::Hex => bytes.iter().map(|b| format!("{b:02x}")).collect(),
+            Format::SpacedHex => join(bytes, " ", |b| format!("{b:02x}")),
+            Format::CArray => format!(
+                "unsigned char data[{}] = {{ {} }};",
+                bytes.len(),
+                join(bytes, ", ", |b| format!("0x{b:02x}"))
+            ),
+            Format::Rust => format!(
+                "let data: [u8; {}] = [{}];",
+                bytes.len(),
+                join(bytes, ", ", |b| format!("0x{b:02x}"))
+            ),
+            Format::Python => format!("b\"{}\"", escape(bytes)),
+            Format::Base64 => STANDARD.encode(bytes),
+            Format::Escaped => format!("\"{}\"", escape(bytes)),
+        }
+    }
+}
+
+fn join(bytes: &[u8], separator: &str, f: impl Fn(u8) -> String) -> String {
+    bytes
+        .iter()
+        .map(|b| f(*b))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|b| std::ascii::escape_default(*b))
+        .map(char::from)
+        .collect()
+}
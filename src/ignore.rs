@@ -0,0 +1,213 @@
+use std::ops::Range;
+
+/// Part of the data left out of comparisons, in both files
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mask {
+    /// Whole bytes, which may go past the end of the files
+    Bytes(Range<usize>),
+    /// Bits of the bytes starting at `offset`
+    Bits { offset: usize, bits: Vec<u8> },
+}
+
+impl Mask {
+    /// Ranges of the bytes with at least one ignored bit
+    pub fn ranges(&self) -> Vec<Range<usize>> {
+        let (offset, bits) = match self {
+            Mask::Bytes(range) if range.is_empty() => return vec![],
+            Mask::Bytes(range) => return vec![range.clone()],
+            Mask::Bits { offset, bits } => (*offset, bits),
+        };
+        let mut ranges: Vec<Range<usize>> = vec![];
+
+        for (i, bits) in bits.iter().enumerate() {
+            if *bits == 0 {
+                continue;
+            }
+
+            let position = offset + i;
+            match ranges.last_mut() {
+                Some(last) if last.end == position => last.end += 1,
+                _ => ranges.push(position..position + 1),
+            }
+        }
+
+        ranges
+    }
+}
+
+/// Parses `OFFSET+LENGTH` to ignore whole bytes, or `OFFSET:MASK` with the
+/// ignored bits of each byte in hex
+pub fn parse(s: &str) -> Result<Mask, String> {
+    if let Some((offset, length)) = s.split_once('+') {
+        let offset = crate::number(offset.trim())?;
+        let length = crate::number(length.trim())?;
+
+        let end = offset
+            .checked_add(length)
+            .ok_or_else(|| format!("invalid ignored region {s:?}, past the largest offset"))?;
+
+        return Ok(Mask::Bytes(offset..end));
+    }
+
+    let Some((offset, mask)) = s.split_once(':') else {
+        return Err(format!(
+            "invalid ignored region {s:?}, expected OFFSET+LENGTH or OFFSET:MASK"
+        ));
+    };
+
+    let offset = crate::number(offset.trim())?;
+    let mask = mask.trim();
+    let mask = mask.strip_prefix("0x").unwrap_or(mask);
+    if mask.is_empty() || mask.len() % 2 != 0 {
+        return Err(format!(
+            "invalid mask {mask:?}, expected whole bytes in hex"
+        ));
+    }
+
+    let bits = (0..mask.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&mask[i..i + 2], 16))
+        .collect::<Result<_, _>>()
+        .map_err(|err| format!("invalid mask {mask:?}: {err}"))?;
+
+    Ok(Mask::Bits { offset, bits })
+}
+
+#[derive(clap::Args, Debug)]
+#[group(id = "ignored")]
+pub struct Options {
+    /// Region left out of the comparison, `OFFSET+LENGTH` or `OFFSET:MASK`
+    /// with the ignored bits of each byte in hex
+    #[arg(short, long = "ignore", value_parser = parse)]
+    ignore: Vec<Mask>,
+    /// File with one ignored region per line, `#` starts a comment
+    #[arg(long)]
+    ignore_file: Option<String>,
+}
+
+impl Options {
+    pub fn masks(&self) -> std::io::Result<Vec<Mask>> {
+        let mut masks = self.ignore.clone();
+
+        if let Some(path) = &self.ignore_file {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| std::io::Error::new(err.kind(), format!("{path}: {err}")))?;
+            for line in text.lines() {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let mask = parse(line).map_err(|err| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{path}: {err}"))
+                })?;
+                masks.push(mask);
+            }
+        }
+
+        Ok(masks)
+    }
+}
+
+/// Clears the ignored bits so that the regions compare as equal
+pub fn clear(masks: &[Mask], data: &mut [u8]) {
+    for mask in masks {
+        match mask {
+            Mask::Bytes(range) => {
+                let end = std::cmp::min(range.end, data.len());
+                if let Some(bytes) = data.get_mut(range.start..end) {
+                    bytes.fill(0);
+                }
+            }
+            Mask::Bits { offset, bits } => {
+                let Some(bytes) = data.get_mut(*offset..) else {
+                    continue;
+                };
+                for (byte, bits) in bytes.iter_mut().zip(bits) {
+                    *byte &= !bits;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse("0x10+4"), Ok(Mask::Bytes(0x10..0x14)));
+        assert_eq!(parse(" 16 + 0x4 "), Ok(Mask::Bytes(16..20)));
+        assert_eq!(parse("8+0").unwrap().ranges(), []);
+        assert_eq!(
+            parse("0xffffffffffffffff+2"),
+            Err("invalid ignored region \"0xffffffffffffffff+2\", past the largest offset".into())
+        );
+    }
+
+    #[test]
+    fn masks() {
+        let mask = parse("0x20:0x0f00ff").unwrap();
+        assert_eq!(
+            mask,
+            Mask::Bits {
+                offset: 0x20,
+                bits: vec![0x0f, 0x00, 0xff]
+            }
+        );
+        assert_eq!(mask.ranges(), [0x20..0x21, 0x22..0x23]);
+
+        let mut data = vec![0xff; 0x24];
+        clear(&[mask, Mask::Bytes(0x23..0x30)], &mut data);
+        assert_eq!(data[0x1f..], [0xff, 0xf0, 0xff, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn malformed() {
+        for s in [
+            "", "16", "0x10+", "+4", "ab+1", "0:", "0:f", "0:0xfff", "0:zz", "x:ff",
+        ] {
+            assert!(parse(s).is_err(), "{s:?}");
+        }
+        assert_eq!(
+            parse("16"),
+            Err("invalid ignored region \"16\", expected OFFSET+LENGTH or OFFSET:MASK".into())
+        );
+        assert_eq!(
+            parse("0:fff"),
+            Err("invalid mask \"fff\", expected whole bytes in hex".into())
+        );
+    }
+
+    #[test]
+    fn file() {
+        let path = std::env::temp_dir().join(format!("lazyhex-ignore-{}", std::process::id()));
+        let options = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            Options {
+                ignore: vec![Mask::Bytes(0..1)],
+                ignore_file: Some(path.to_string_lossy().into_owned()),
+            }
+        };
+
+        let masks = options("# header\n\n0x10+2  # checksum\n 4:f0\n").masks();
+        assert_eq!(
+            masks.unwrap(),
+            [
+                Mask::Bytes(0..1),
+                Mask::Bytes(0x10..0x12),
+                Mask::Bits {
+                    offset: 4,
+                    bits: vec![0xf0]
+                }
+            ]
+        );
+
+        let err = options("0x10+2\n0x20\n").masks().unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("\"0x20\""), "{err}");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+
+/// What a key sequence does, named in snake case in the config file
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Unbinds a default key
+    Nop,
+    Quit,
+    Left,
+    Down,
+    Up,
+    Right,
+    Insert,
+    Append,
+    Visual,
+    /// Back to normal mode, discards a patch preview in normal mode
+    Escape,
+    Write,
+    /// Operator in normal mode, deletes the selection in visual mode
+    Delete,
+    /// Deletes the bytes under the cursor
+    DeleteByte,
+    Highlight,
+    Start,
+    End,
+    LineStart,
+    LineEnd,
+    /// Start of the next word, see `word` in the config
+    WordNext,
+    WordPrev,
+    /// Start of the next run of zero bytes
+    ZeroNext,
+    ZeroPrev,
+    HalfPageDown,
+    HalfPageUp,
+    PageDown,
+    PageUp,
+    /// Scrolls to put the cursor in the middle of the view
+    ScrollCenter,
+    ScrollTop,
+    ScrollBottom,
+    Yank,
+    Register,
+    PasteAfter,
+    PasteBefore,
+    Undo,
+    Redo,
+    /// Adds the count to the selected bytes
+    Increment,
+    Decrement,
+    ApplyPatch,
+    /// Starts recording a macro in the register typed next, or stops recording
+    Record,
+    /// Plays the macro of the register typed next, `@` plays the last one again
+    Play,
+    /// Repeats the last command that edited the buffer
+    Repeat,
+    /// Types a command like `source script.rhai`, `w` or `q`
+    CommandLine,
+    NextHunk,
+    PrevHunk,
+    Obtain,
+    Put,
+    CycleAlgorithm,
+    SwitchFocus,
+    ToggleSync,
+}
+
+impl Action {
+    /// Whether the action moves the cursor, and can follow an operator
+    pub fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            Action::Left
+                | Action::Down
+                | Action::Up
+                | Action::Right
+                | Action::Start
+                | Action::End
+                | Action::LineStart
+                | Action::LineEnd
+                | Action::WordNext
+                | Action::WordPrev
+                | Action::ZeroNext
+                | Action::ZeroPrev
+        )
+    }
+
+    /// Whether the action waits for a motion in normal mode
+    pub fn is_operator(&self) -> bool {
+        matches!(self, Action::Delete | Action::Yank)
+    }
+}
+
+/// A key with the modifiers that matter for bindings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Self {
+            code: event.code,
+            ctrl: event.modifiers.contains(KeyModifiers::CONTROL),
+        }
+    }
+}
+
+impl Key {
+    pub fn char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c) if !self.ctrl => Some(c),
+            _ => None,
+        }
+    }
+}
+
+fn special(name: &str) -> Option<KeyCode> {
+    let code = match name.to_ascii_lowercase().as_str() {
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "cr" | "enter" => KeyCode::Enter,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" => KeyCode::Delete,
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// Parses a key sequence written like `]c`, `<Esc>` or `<C-r>`
+pub fn parse(s: &str) -> Result<Vec<Key>, String> {
+    let mut keys = vec![];
+    let mut rest = s;
+
+    while let Some(c) = rest.chars().next() {
+        let name = rest
+            .strip_prefix('<')
+            .and_then(|r| r.split_once('>'))
+            .filter(|(name, _)| !name.is_empty());
+
+        let Some((name, after)) = name else {
+            keys.push(Key {
+                code: KeyCode::Char(c),
+                ctrl: false,
+            });
+            rest = &rest[c.len_utf8()..];
+            continue;
+        };
+
+        let key = match name.split_once('-') {
+            Some((modifier, key)) if modifier.eq_ignore_ascii_case("c") => {
+                let code = match key.chars().count() {
+                    1 => KeyCode::Char(key.chars().next().unwrap().to_ascii_lowercase()),
+                    _ => special(key).ok_or_else(|| format!("unknown key <{key}> in {s:?}"))?,
+                };
+                Key { code, ctrl: true }
+            }
+            _ => Key {
+                code: special(name).ok_or_else(|| format!("unknown key <{name}> in {s:?}"))?,
+                ctrl: false,
+            },
+        };
+        keys.push(key);
+        rest = after;
+    }
+
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+
+    Ok(keys)
+}
+
+/// A key sequence read from the config file
+#[derive(Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Sequence(pub Vec<Key>);
+
+impl TryFrom<String> for Sequence {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        parse(&s).map(Sequence)
+    }
+}
+
+pub type Bindings = HashMap<Vec<Key>, Action>;
+
+/// Bindings of each mode, `compare` ones take precedence in normal mode when comparing files
+pub struct Keymap {
+    pub normal: Bindings,
+    pub visual: Bindings,
+    pub insert: Bindings,
+    pub compare: Bindings,
+}
+
+const NORMAL: &[(&str, Action)] = &[
+    ("<C-q>", Action::Quit),
+    ("ZQ", Action::Quit),
+    ("h", Action::Left),
+    ("j", Action::Down),
+    ("k", Action::Up),
+    ("l", Action::Right),
+    ("i", Action::Insert),
+    ("<C-s>", Action::Write),
+    ("d", Action::Delete),
+    ("x", Action::DeleteByte),
+    ("y", Action::Yank),
+    ("o", Action::Append),
+    ("v", Action::Visual),
+    ("H", Action::Highlight),
+    ("g", Action::Start),
+    ("G", Action::End),
+    ("0", Action::LineStart),
+    ("$", Action::LineEnd),
+    ("w", Action::WordNext),
+    ("b", Action::WordPrev),
+    ("}", Action::ZeroNext),
+    ("{", Action::ZeroPrev),
+    ("<C-d>", Action::HalfPageDown),
+    ("<C-u>", Action::HalfPageUp),
+    ("<C-f>", Action::PageDown),
+    ("<C-b>", Action::PageUp),
+    ("<PageDown>", Action::PageDown),
+    ("<PageUp>", Action::PageUp),
+    ("zz", Action::ScrollCenter),
+    ("zt", Action::ScrollTop),
+    ("zb", Action::ScrollBottom),
+    ("\"", Action::Register),
+    ("p", Action::PasteAfter),
+    ("P", Action::PasteBefore),
+    ("u", Action::Undo),
+    ("U", Action::Redo),
+    ("<C-a>", Action::Increment),
+    ("<C-x>", Action::Decrement),
+    ("A", Action::ApplyPatch),
+    ("q", Action::Record),
+    ("@", Action::Play),
+    (".", Action::Repeat),
+    (":", Action::CommandLine),
+    ("<Esc>", Action::Escape),
+];
+
+const VISUAL: &[(&str, Action)] = &[
+    ("<C-q>", Action::Quit),
+    ("q", Action::Record),
+    ("h", Action::Left),
+    ("j", Action::Down),
+    ("k", Action::Up),
+    ("l", Action::Right),
+    ("i", Action::Insert),
+    ("d", Action::Delete),
+    ("H", Action::Highlight),
+    ("g", Action::Start),
+    ("G", Action::End),
+    ("0", Action::LineStart),
+    ("$", Action::LineEnd),
+    ("w", Action::WordNext),
+    ("b", Action::WordPrev),
+    ("}", Action::ZeroNext),
+    ("{", Action::ZeroPrev),
+    ("<C-d>", Action::HalfPageDown),
+    ("<C-u>", Action::HalfPageUp),
+    ("<C-f>", Action::PageDown),
+    ("<C-b>", Action::PageUp),
+    ("<PageDown>", Action::PageDown),
+    ("<PageUp>", Action::PageUp),
+    ("zz", Action::ScrollCenter),
+    ("zt", Action::ScrollTop),
+    ("zb", Action::ScrollBottom),
+    ("y", Action::Yank),
+    ("\"", Action::Register),
+    (":", Action::CommandLine),
+    ("<C-a>", Action::Increment),
+    ("<C-x>", Action::Decrement),
+    ("<Esc>", Action::Escape),
+];
+
+const INSERT: &[(&str, Action)] = &[("<Esc>", Action::Escape)];
+
+/// Looked up before the normal mode table, `do` and `dp` make a lone `d` wait
+/// for a second key. Any other key then runs `d` as the delete operator and is
+/// read again as its count or motion, the same as in the viewer.
+const COMPARE: &[(&str, Action)] = &[
+    ("]c", Action::NextHunk),
+    ("[c", Action::PrevHunk),
+    ("n", Action::NextHunk),
+    ("N", Action::PrevHunk),
+    ("do", Action::Obtain),
+    ("dp", Action::Put),
+    ("a", Action::CycleAlgorithm),
+    ("<Tab>", Action::SwitchFocus),
+    ("s", Action::ToggleSync),
+];
+
+fn bindings(defaults: &[(&str, Action)]) -> Bindings {
+    defaults
+        .iter()
+        .map(|(keys, action)| (parse(keys).unwrap(), *action))
+        .collect()
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            normal: bindings(NORMAL),
+            visual: bindings(VISUAL),
+            insert: bindings(INSERT),
+            compare: bindings(COMPARE),
+        }
+    }
+}
+
+pub enum Lookup {
+    Action(Action),
+    /// The keys start a longer sequence, with the action bound to them if any
+    Pending(Option<Action>),
+    None,
+}
+
+/// Finds the action of a key sequence in the first of `tables` binding it
+pub fn lookup(tables: &[&Bindings], keys: &[Key]) -> Lookup {
+    let longer = tables.iter().any(|table| {
+        table
+            .keys()
+            .any(|sequence| sequence.len() > keys.len() && sequence.starts_with(keys))
+    });
+    let action = tables.iter().find_map(|table| table.get(keys)).copied();
+
+    match (longer, action) {
+        (true, action) => Lookup::Pending(action),
+        (false, Some(action)) => Lookup::Action(action),
+        (false, None) => Lookup::None,
+    }
+}
+
+/// What a sequence of keys stands for
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    /// An action with its count, 1 when none was typed
+    Action(Action, usize),
+    /// An operator applied from the cursor to where the motion goes, or to
+    /// `count` rows when the operator was doubled
+    Operator {
+        operator: Action,
+        motion: Option<Action>,
+        count: usize,
+    },
+    /// A key bound to nothing
+    Key(Key),
+    /// Writes the differences of the compared files as a patch to this path,
+    /// typed as `:writepatch`
+    WritePatch(String),
+}
+
+/// Reads counts, operators and key sequences as they are typed
+#[derive(Default)]
+pub struct Parser {
+    count: Option<usize>,
+    /// Operator waiting for a motion, with its count
+    operator: Option<(Action, usize)>,
+    keys: Vec<Key>,
+}
+
+impl Parser {
+    /// Feeds a key looked up in `tables`, counts are only read with `counts`
+    /// and operators only with the `operators` table their motions come from
+    pub fn feed(
+        &mut self,
+        key: Key,
+        tables: &[&Bindings],
+        operators: Option<&Bindings>,
+        counts: bool,
+    ) -> Vec<Input> {
+        let mut inputs = vec![];
+        self.push(key, tables, operators, counts, &mut inputs);
+        inputs
+    }
+
+    /// Whether no count, operator or key sequence is waiting for more keys
+    pub fn idle(&self) -> bool {
+        self.count.is_none() && self.operator.is_none() && self.keys.is_empty()
+    }
+
+    fn push(
+        &mut self,
+        key: Key,
+        tables: &[&Bindings],
+        operators: Option<&Bindings>,
+        counts: bool,
+        inputs: &mut Vec<Input>,
+    ) {
+        if counts && self.keys.is_empty() {
+            let digit = key.char().and_then(|c| c.to_digit(10));
+            // A 0 alone is a motion
+            if let Some(digit) = digit.filter(|d| *d != 0 || self.count.is_some()) {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return;
+            }
+        }
+
+        let motions;
+        let lookup_tables = match (self.operator, operators) {
+            (Some(_), Some(table)) => {
+                motions = [table];
+                &motions[..]
+            }
+            _ => tables,
+        };
+
+        self.keys.push(key);
+        match lookup(lookup_tables, &self.keys) {
+            Lookup::Action(action) => {
+                self.keys.clear();
+                self.resolve(action, operators.is_some(), inputs);
+            }
+            Lookup::Pending(_) => {}
+            Lookup::None if self.keys.len() == 1 => {
+                self.keys.clear();
+                self.count = None;
+                self.operator = None;
+                inputs.push(Input::Key(key));
+            }
+            // Runs the start of the sequence if it is bound, then starts again
+            // from the last key
+            Lookup::None => {
+                let keys = std::mem::take(&mut self.keys);
+                match lookup(lookup_tables, &keys[..keys.len() - 1]) {
+                    Lookup::Pending(Some(action)) => {
+                        self.resolve(action, operators.is_some(), inputs)
+                    }
+                    _ => {
+                        self.count = None;
+                        self.operator = None;
+                    }
+                }
+                self.push(key, tables, operators, counts, inputs);
+            }
+        }
+    }
+
+    fn resolve(&mut self, action: Action, operators: bool, inputs: &mut Vec<Input>) {
+        let count = self.count.take();
+
+        match self.operator.take() {
+            Some((operator, first)) => {
+                let count = first.saturating_mul(count.unwrap_or(1));
+                if action == operator {
+                    inputs.push(Input::Operator {
+                        operator,
+                        motion: None,
+                        count,
+                    });
+                } else if action.is_motion() {
+                    inputs.push(Input::Operator {
+                        operator,
+                        motion: Some(action),
+                        count,
+                    });
+                }
+                // Anything else cancels the operator
+            }
+            None if operators && action.is_operator() => {
+                self.operator = Some((action, count.unwrap_or(1)));
+            }
+            None => inputs.push(Input::Action(action, count.unwrap_or(1))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(keys: &str, tables: &[&Bindings], operators: &Bindings) -> Vec<Input> {
+        let mut parser = Parser::default();
+        parse(keys)
+            .unwrap()
+            .into_iter()
+            .flat_map(|key| parser.feed(key, tables, Some(operators), true))
+            .collect()
+    }
+
+    #[test]
+    fn compare_delete() {
+        let keymap = Keymap::default();
+        let (normal, compare) = (&keymap.normal, &keymap.compare);
+
+        for keys in [
+            "dl", "d3l", "2dw", "2d3l", "dd", "3dd", "d<Esc>l", "dx", "x",
+        ] {
+            assert_eq!(
+                feed(keys, &[compare, normal], normal),
+                feed(keys, &[normal], normal),
+                "{keys}"
+            );
+        }
+
+        assert_eq!(
+            feed("dodp", &[compare, normal], normal),
+            [
+                Input::Action(Action::Obtain, 1),
+                Input::Action(Action::Put, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn counts() {
+        let keymap = Keymap::default();
+        let normal = &keymap.normal;
+        let delete = |motion, count| Input::Operator {
+            operator: Action::Delete,
+            motion,
+            count,
+        };
+
+        for (keys, inputs) in [
+            ("3x", vec![Input::Action(Action::DeleteByte, 3)]),
+            ("10l", vec![Input::Action(Action::Right, 10)]),
+            ("0", vec![Input::Action(Action::LineStart, 1)]),
+            ("2d3w", vec![delete(Some(Action::WordNext), 6)]),
+            ("d0", vec![delete(Some(Action::LineStart), 1)]),
+            ("3dd", vec![delete(None, 3)]),
+            ("d2d", vec![delete(None, 2)]),
+            // The count goes with the operator it cancels
+            ("2dyx", vec![Input::Action(Action::DeleteByte, 1)]),
+            (
+                "2<Esc>x",
+                vec![
+                    Input::Action(Action::Escape, 2),
+                    Input::Action(Action::DeleteByte, 1),
+                ],
+            ),
+            (
+                "99999999999999999999999x",
+                vec![Input::Action(Action::DeleteByte, usize::MAX)],
+            ),
+        ] {
+            assert_eq!(feed(keys, &[normal], normal), inputs, "{keys}");
+        }
+    }
+
+    #[test]
+    fn macros() {
+        let keymap = Keymap::default();
+        let normal = &keymap.normal;
+
+        assert_eq!(
+            feed("q3@2.", &[normal], normal),
+            [
+                Input::Action(Action::Record, 1),
+                Input::Action(Action::Play, 3),
+                Input::Action(Action::Repeat, 2)
+            ]
+        );
+    }
+}
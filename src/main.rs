@@ -1,127 +1,843 @@
+mod checksum;
 mod comparator;
+mod config;
+mod diff;
+mod dump;
+mod entropy;
+mod format;
+mod ignore;
+mod keymap;
+mod merge;
+mod operation;
+mod panes;
+mod patch;
+mod script;
 mod ui;
 mod viewer;
 
-use comparator::Comparator;
+use checksum::{hex, Checksum, Panel};
+use comparator::{Algorithm, Comparator};
+use config::{Config, Endian};
+use format::Format;
+use keymap::{Action, Input, Key};
+use merge::Merge;
+use operation::Operation;
+use panes::Panes;
 use ratatui::{
     prelude::{Backend, CrosstermBackend},
-    Terminal,
+    Frame, Terminal,
 };
-use ui::{comparator_ui, viewer_ui};
+use ui::{comparator_ui, merge_ui, viewer_ui, Target};
 use viewer::{Mode, Viewer};
 
 use std::{
+    collections::HashMap,
     error::Error,
+    fs::OpenOptions,
+    io::{IsTerminal, Read, Write},
     time::{Duration, Instant},
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, MouseButton,
+        MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 
-fn run_viewer<B: Backend>(
-    terminal: &mut Terminal<B>,
-    mut viewer: Viewer,
-    tick_rate: Duration,
-) -> std::io::Result<()> {
-    let mut last_tick = Instant::now();
+/// Keys waiting for the rest of a command
+#[derive(Default)]
+struct Pending {
+    input: Option<u32>,
+    register: bool,
+    parser: keymap::Parser,
+    /// Where the mouse button was pressed, for a drag to select from there
+    anchor: Option<usize>,
+    /// The next key names the register of a macro
+    name: Option<Macro>,
+    /// Register and keys of the macro being recorded
+    recording: Option<(char, Vec<Key>)>,
+    macros: HashMap<char, Vec<Key>>,
+    /// Register of the last macro played, for `@@`
+    played: Option<char>,
+    /// Macros and repeats being played, their keys are not recorded again
+    depth: usize,
+    /// Keys of the command being typed, with the version of the buffers
+    /// before it, to be repeated by `.` if it edits them
+    command: Vec<Key>,
+    version: usize,
+    /// Whether the command being typed undoes or repeats, which `.` never repeats
+    skip: bool,
+    /// Whether `.` is repeating, the last edit then stays the same
+    repeating: bool,
+    /// Keys of the last command that edited a buffer
+    edit: Vec<Key>,
+    /// Command typed after `:`
+    line: String,
+}
 
-    let mut input = None;
+/// What the register named by the next key is for
+#[derive(Clone, Copy)]
+enum Macro {
+    Record,
+    /// Plays the macro the number of times typed before `@`
+    Play(usize),
+}
 
-    loop {
-        terminal.draw(|f| viewer_ui(f, &mut viewer))?;
+/// Macros and repeats played inside each other, a macro playing itself stops there
+const DEPTH: usize = 100;
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match (&viewer.mode, key.code) {
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('q')) => return Ok(()),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('h')) => viewer.left(),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('j')) => viewer.down(),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('k')) => viewer.up(),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('l')) => viewer.right(),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('i')) => {
-                            viewer.mode = Mode::Insert
-                        }
-                        (Mode::Normal, KeyCode::Char('w')) => viewer.flush(),
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('d')) => {
-                            viewer.delete();
-                            viewer.selection.set(viewer.selection.start);
-                            viewer.mode = Mode::Normal;
-                            if viewer.selection.end == viewer.data.len() {
-                                viewer.left();
-                            }
+/// Parses what was typed with a key pressed in a viewer, handling register
+/// names and yank formats on the way
+fn handle(viewer: &mut Viewer, key: Key, pending: &mut Pending, compare: bool) -> Vec<Input> {
+    if pending.register {
+        pending.register = false;
+        if let Some(c) = key.char() {
+            if !viewer.select_register(c) {
+                viewer.status = Some(format!("invalid register \"{c}"));
+            }
+        }
+        return vec![];
+    }
+
+    if viewer.mode == Mode::Yank {
+        match (key.code, key.char()) {
+            (KeyCode::Esc, _) => return vec![Input::Action(Action::Escape, 1)],
+            (_, Some(c)) => {
+                let format = Format::from_key(c);
+                if c == 'y' || format.is_some() {
+                    viewer.yank(format);
+                    viewer.selection.set(viewer.selection.start);
+                    viewer.mode = Mode::Normal;
+                }
+            }
+            _ => {}
+        }
+        return vec![];
+    }
+
+    if viewer.mode == Mode::Checksum {
+        let Some(panel) = &mut viewer.checksums else {
+            return vec![];
+        };
+        let close = match (key.code, key.char()) {
+            (KeyCode::Down, _) | (_, Some('j')) => {
+                panel.selected = std::cmp::min(panel.selected + 1, panel.values.len() - 1);
+                false
+            }
+            (KeyCode::Up, _) | (_, Some('k')) => {
+                panel.selected = panel.selected.saturating_sub(1);
+                false
+            }
+            (KeyCode::Enter, _) | (_, Some('y')) => {
+                let value = panel.values[panel.selected].1.clone();
+                viewer.yank_bytes(value, Some(Format::Hex));
+                true
+            }
+            (KeyCode::Esc, _) | (_, Some('q')) => true,
+            _ => false,
+        };
+        if close {
+            viewer.checksums = None;
+            viewer.mode = Mode::Normal;
+            viewer.selection.set(viewer.selection.end);
+        }
+        return vec![];
+    }
+
+    if let Mode::Command { .. } = viewer.mode {
+        match key.code {
+            KeyCode::Esc => return vec![Input::Action(Action::Escape, 1)],
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut pending.line);
+                let inputs = command(viewer, &line);
+                if let Mode::Command { .. } = viewer.mode {
+                    viewer.mode = Mode::Normal;
+                    viewer.selection.set(viewer.selection.end);
+                }
+                return inputs;
+            }
+            // Erasing past the start leaves the command line
+            KeyCode::Backspace if pending.line.pop().is_none() => viewer.mode = Mode::Normal,
+            _ => pending.line.extend(key.char()),
+        }
+        if let Mode::Command { .. } = viewer.mode {
+            viewer.status = Some(format!(":{}", pending.line));
+        }
+        return vec![];
+    }
+
+    let keys = &viewer.config.keys;
+    let (tables, operators, counts) = match viewer.mode {
+        Mode::Normal if compare => (vec![&keys.compare, &keys.normal], Some(&keys.normal), true),
+        Mode::Normal => (vec![&keys.normal], Some(&keys.normal), true),
+        Mode::Visual => (vec![&keys.visual], None, true),
+        Mode::Insert | Mode::Yank | Mode::Command { .. } | Mode::Checksum => {
+            (vec![&keys.insert], None, false)
+        }
+    };
+
+    pending.parser.feed(key, &tables, operators, counts)
+}
+
+/// Computes the checksum named first over the selection, or the whole data,
+/// then writes it at the offset given after in the byte order given last
+fn checksum(viewer: &mut Viewer, argument: &str) -> Result<String, String> {
+    let mut words = argument.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let checksum = Checksum::from_name(name).ok_or_else(|| format!("unknown checksum {name:?}"))?;
+    let mut value = checksum.compute(&viewer.data[viewer.selection_or_all()]);
+
+    let Some(offset) = words.next() else {
+        return Ok(format!("{name} {}", hex(&value)));
+    };
+    let offset = number(offset)?;
+    let endian = match words.next() {
+        None => viewer.endian,
+        Some("le" | "little") => Endian::Little,
+        Some("be" | "big") => Endian::Big,
+        Some(other) => return Err(format!("invalid byte order {other:?}, expected le or be")),
+    };
+
+    if checksum.is_number() && endian == Endian::Little {
+        value.reverse();
+    }
+    let range = offset..offset.saturating_add(value.len());
+    if range.end > viewer.data.len() {
+        return Err(format!(
+            "{} bytes at {offset:#x} go past the end",
+            value.len()
+        ));
+    }
+    viewer.replace(vec![(range, value.clone())]);
+
+    Ok(format!("wrote {name} {} at {offset:#x}", hex(&value)))
+}
+
+/// Runs a command typed after `:`
+fn command(viewer: &mut Viewer, line: &str) -> Vec<Input> {
+    let line = line.trim();
+    let (name, argument) = match line.split_once(' ') {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (line, ""),
+    };
+
+    match (name, argument) {
+        ("", _) => {}
+        ("q" | "quit", "") => return vec![Input::Action(Action::Quit, 1)],
+        ("w" | "write", "") => return vec![Input::Action(Action::Write, 1)],
+        ("checksum" | "hash", "") => {
+            viewer.checksums = Some(Panel::new(viewer.data, viewer.selection_or_all()));
+            viewer.mode = Mode::Checksum;
+        }
+        ("checksum" | "hash", argument) => {
+            viewer.status = Some(checksum(viewer, argument).unwrap_or_else(|err| err))
+        }
+        ("patch", "") => viewer.status = Some("patch needs a file".to_string()),
+        ("patch", path) => {
+            let edits = std::fs::read(path).and_then(|patch| patch::edits(&patch, viewer.data));
+            match edits {
+                Ok(edits) => viewer.preview(edits),
+                Err(err) => viewer.status = Some(format!("{path}: {err}")),
+            }
+        }
+        ("wp" | "writepatch", "") => viewer.status = Some("writepatch needs a file".to_string()),
+        ("wp" | "writepatch", path) => return vec![Input::WritePatch(path.to_string())],
+        ("so" | "source", "") => viewer.status = Some("source needs a script".to_string()),
+        ("so" | "source", path) => {
+            viewer.status = Some(match script::run(viewer, path, true) {
+                Ok(output) => match output.printed.last() {
+                    Some(line) => line.clone(),
+                    None => format!("sourced {path}"),
+                },
+                Err(err) => err,
+            })
+        }
+        (name, argument) => match Operation::parse(name, argument) {
+            Some(Ok(operation)) => {
+                let length = viewer.selected().len();
+                viewer.transform(|bytes| operation.apply(bytes));
+                viewer.status = Some(format!("{name} on {length} bytes"));
+            }
+            Some(Err(err)) => viewer.status = Some(err),
+            None => viewer.status = Some(format!("unknown command :{line}")),
+        },
+    }
+
+    vec![]
+}
+
+/// Where a motion repeated `count` times goes from the cursor, past the end
+/// when it leaves the data
+fn motion(viewer: &Viewer, motion: Action, count: usize) -> usize {
+    let cursor = viewer.selection.end;
+    let (width, word) = (viewer.width, viewer.config.word);
+
+    match motion {
+        Action::Left => cursor.saturating_sub(count),
+        Action::Right => cursor.saturating_add(count),
+        Action::Up => cursor.saturating_sub(count.saturating_mul(width)),
+        Action::Down => cursor.saturating_add(count.saturating_mul(width)),
+        Action::Start => 0,
+        Action::End => viewer.len() - 1,
+        Action::LineStart => cursor - cursor % width,
+        Action::LineEnd => cursor - cursor % width + width - 1,
+        Action::WordNext => (cursor / word).saturating_add(count).saturating_mul(word),
+        Action::WordPrev => cursor.div_ceil(word).saturating_sub(count) * word,
+        Action::ZeroNext => viewer.zeros_after(cursor, count),
+        Action::ZeroPrev => viewer.zeros_before(cursor, count),
+        _ => cursor,
+    }
+}
+
+/// Applies an operator on the bytes between the cursor and where the motion
+/// goes, or on whole rows without a motion
+fn operate(
+    viewer: &mut Viewer,
+    operator: Action,
+    motion: Option<Action>,
+    count: usize,
+    pending: &mut Pending,
+) -> bool {
+    let cursor = viewer.selection.end;
+    let range = match motion {
+        Some(action) => {
+            let target = self::motion(viewer, action, count);
+            let inclusive = matches!(action, Action::End | Action::LineEnd);
+            if target >= cursor {
+                cursor..target.saturating_add(usize::from(inclusive))
+            } else {
+                target..cursor
+            }
+        }
+        None => {
+            let start = cursor - cursor % viewer.width;
+            start..start.saturating_add(count.saturating_mul(viewer.width))
+        }
+    };
+    let range = range.start..std::cmp::min(range.end, viewer.len());
+    if range.is_empty() {
+        return true;
+    }
+
+    viewer.selection.start = range.start;
+    viewer.selection.end = range.end - 1;
+    viewer.mode = Mode::Visual;
+    perform(viewer, Input::Action(operator, 1), pending)
+}
+
+/// Runs what was typed on a viewer, returns false to quit
+fn perform(viewer: &mut Viewer, input: Input, pending: &mut Pending) -> bool {
+    let (action, count) = match input {
+        Input::Action(action, count) => (action, count),
+        Input::Operator {
+            operator,
+            motion,
+            count,
+        } => return operate(viewer, operator, motion, count, pending),
+        Input::WritePatch(_) => {
+            viewer.status = Some("writepatch needs two files to compare".to_string());
+            return true;
+        }
+        // Typed as hex in insert mode
+        Input::Key(key) => {
+            if viewer.mode == Mode::Insert {
+                match (pending.input, key.char().and_then(|c| c.to_digit(16))) {
+                    (None, Some(b)) => pending.input = Some(b),
+                    (Some(a), Some(b)) => {
+                        viewer.set((a * 16 + b) as u8);
+                        viewer.right();
+                        pending.input = None;
+                    }
+                    _ => {}
+                }
+            }
+            return true;
+        }
+    };
+
+    match action {
+        Action::Quit => return false,
+        Action::Insert => viewer.mode = Mode::Insert,
+        Action::Append => {
+            viewer.append();
+            viewer.mode = Mode::Insert;
+            viewer.right();
+        }
+        Action::Visual => viewer.mode = Mode::Visual,
+        Action::Escape => {
+            if viewer.mode == Mode::Normal {
+                viewer.discard();
+            }
+            viewer.mode = Mode::Normal;
+            pending.input = None;
+            pending.line.clear();
+        }
+        Action::Write => viewer.flush(),
+        Action::Delete => {
+            viewer.delete();
+            viewer.selection.set(viewer.selection.start);
+            viewer.mode = Mode::Normal;
+            if viewer.selection.end == viewer.len() {
+                viewer.left();
+            }
+        }
+        Action::DeleteByte => {
+            return operate(viewer, Action::Delete, Some(Action::Right), count, pending)
+        }
+        Action::Highlight => {
+            viewer.highlight();
+            viewer.mode = Mode::Normal;
+        }
+        Action::HalfPageDown | Action::HalfPageUp | Action::PageDown | Action::PageUp => {
+            // Counts past the rows of the buffer move no further
+            let count = std::cmp::min(count, viewer.len().div_ceil(viewer.width)) as isize;
+            let (half, page) = viewer.pages();
+            viewer.page(match action {
+                Action::HalfPageDown => half * count,
+                Action::HalfPageUp => -half * count,
+                Action::PageDown => page * count,
+                _ => -page * count,
+            })
+        }
+        Action::ScrollCenter => viewer.scroll_center(),
+        Action::ScrollTop => viewer.scroll_top(),
+        Action::ScrollBottom => viewer.scroll_bottom(),
+        Action::Yank => viewer.mode = Mode::Yank,
+        Action::Register => pending.register = true,
+        Action::PasteAfter => viewer.paste(true, count),
+        Action::PasteBefore => viewer.paste(false, count),
+        // Stops at the end of the history
+        Action::Undo => for _ in (0..count).take_while(|_| viewer.undo()) {},
+        Action::Redo => for _ in (0..count).take_while(|_| viewer.redo()) {},
+        Action::Increment | Action::Decrement => {
+            // Adding 256 is adding nothing, like on the byte itself
+            let count = (count % 256) as u8;
+            let step = match action {
+                Action::Increment => count,
+                _ => count.wrapping_neg(),
+            };
+            viewer.transform(|bytes| Operation::Add(vec![step]).apply(bytes));
+            viewer.selection.set(viewer.selection.start);
+            viewer.mode = Mode::Normal;
+        }
+        Action::ApplyPatch => viewer.apply(),
+        Action::CommandLine => {
+            viewer.mode = Mode::Command {
+                visual: viewer.mode == Mode::Visual,
+            };
+            viewer.status = Some(":".to_string());
+        }
+        // Handled by `press`, which can play keys again
+        Action::Record | Action::Play | Action::Repeat => {}
+        Action::Left
+        | Action::Down
+        | Action::Up
+        | Action::Right
+        | Action::Start
+        | Action::End
+        | Action::LineStart
+        | Action::LineEnd
+        | Action::WordNext
+        | Action::WordPrev
+        | Action::ZeroNext
+        | Action::ZeroPrev => viewer.go(motion(viewer, action, count)),
+        // Only meaningful when comparing files
+        Action::Nop
+        | Action::NextHunk
+        | Action::PrevHunk
+        | Action::Obtain
+        | Action::Put
+        | Action::CycleAlgorithm
+        | Action::SwitchFocus
+        | Action::ToggleSync => {}
+    }
+
+    true
+}
+
+/// Rows scrolled by a turn of the mouse wheel
+const SCROLL: isize = 3;
+
+/// Handles a mouse event on a viewer, returns false if it pointed at nothing in it
+fn mouse(viewer: &mut Viewer, event: MouseEvent, pending: &mut Pending) -> bool {
+    let target = ui::target(viewer, event.column, event.row);
+
+    match (event.kind, target) {
+        (MouseEventKind::ScrollDown, _) => viewer.scroll(SCROLL),
+        (MouseEventKind::ScrollUp, _) => viewer.scroll(-SCROLL),
+        (MouseEventKind::Down(MouseButton::Left), Some(Target::Byte(position))) => {
+            if viewer.mode != Mode::Insert {
+                viewer.mode = Mode::Normal;
+            }
+            viewer.selection.set(position);
+            pending.input = None;
+            pending.anchor = Some(position);
+        }
+        // Selects the bytes read by the row of the info panel
+        (MouseEventKind::Down(MouseButton::Left), Some(Target::Value(size))) => {
+            viewer.mode = Mode::Visual;
+            let start = viewer.selection.start;
+            viewer.selection.end = std::cmp::min(start + size, viewer.len()) - 1;
+        }
+        // Jumps to the block of the entropy panel
+        (MouseEventKind::Down(MouseButton::Left), Some(Target::Block(position))) => {
+            if viewer.mode != Mode::Insert {
+                viewer.mode = Mode::Normal;
+            }
+            viewer.selection.set(position);
+            pending.input = None;
+            viewer.scroll_center();
+        }
+        (MouseEventKind::Drag(MouseButton::Left), Some(Target::Byte(position))) => {
+            let Some(anchor) = pending.anchor else {
+                return false;
+            };
+            viewer.mode = Mode::Visual;
+            viewer.selection.start = std::cmp::min(anchor, position);
+            viewer.selection.end = std::cmp::max(anchor, position);
+        }
+        (MouseEventKind::Up(MouseButton::Left), _) => pending.anchor = None,
+        _ => return false,
+    }
+
+    true
+}
+
+/// What typed keys act on: a viewer alone or the panes of a comparison
+trait Handler<'a> {
+    /// Whether the comparison bindings apply in normal mode
+    const COMPARE: bool;
+
+    fn focused_mut(&mut self) -> &mut Viewer<'a>;
+
+    /// Sum of the versions of the buffers, changing with every edit
+    fn version(&self) -> usize;
+
+    /// Runs what was typed, returns false to quit
+    fn run(&mut self, input: Input, pending: &mut Pending) -> bool;
+
+    /// Brings the other panes up to date after a key or a click
+    fn update(&mut self) {}
+
+    /// Focuses the pane drawn at `column` and `row` of the terminal
+    fn click(&mut self, _column: u16, _row: u16) {}
+
+    /// Takes the results of the work done in the background
+    fn poll(&mut self) {}
+
+    /// Whether work is still done in the background, to draw its result soon
+    fn busy(&self) -> bool;
+
+    fn draw<B: Backend>(&mut self, frame: &mut Frame<B>);
+}
+
+impl<'a> Handler<'a> for Viewer<'a> {
+    const COMPARE: bool = false;
+
+    fn focused_mut(&mut self) -> &mut Viewer<'a> {
+        self
+    }
+
+    fn version(&self) -> usize {
+        self.version
+    }
+
+    fn run(&mut self, input: Input, pending: &mut Pending) -> bool {
+        perform(self, input, pending)
+    }
+
+    fn busy(&self) -> bool {
+        self.entropy.busy()
+    }
+
+    fn draw<B: Backend>(&mut self, frame: &mut Frame<B>) {
+        viewer_ui(frame, self)
+    }
+}
+
+/// Runs what was typed on compared panes, returns false to quit
+fn compare<T: Send + 'static, const N: usize>(
+    panes: &mut Panes<T, N>,
+    input: Input,
+    pending: &mut Pending,
+) -> bool {
+    match input {
+        Input::Action(Action::NextHunk, count) => panes.next_hunk(count),
+        Input::Action(Action::PrevHunk, count) => panes.prev_hunk(count),
+        Input::Action(Action::CycleAlgorithm, _) => panes.algorithm = panes.algorithm.next(),
+        Input::Action(Action::SwitchFocus, _) => panes.switch(),
+        Input::Action(Action::ToggleSync, _) => panes.toggle_sync(),
+        input => return perform(panes.focused_mut(), input, pending),
+    }
+
+    true
+}
+
+impl<'a> Handler<'a> for Comparator<'a> {
+    const COMPARE: bool = true;
+
+    fn focused_mut(&mut self) -> &mut Viewer<'a> {
+        self.panes.focused_mut()
+    }
+
+    fn version(&self) -> usize {
+        self.panes.version()
+    }
+
+    fn run(&mut self, input: Input, pending: &mut Pending) -> bool {
+        match input {
+            Input::Action(Action::Obtain, _) => self.obtain(),
+            Input::Action(Action::Put, _) => self.put(),
+            Input::WritePatch(path) => self.write_patch(&path),
+            input => return compare(&mut self.panes, input, pending),
+        }
+
+        true
+    }
+
+    fn update(&mut self) {
+        self.panes.sync();
+        self.refresh();
+    }
+
+    fn click(&mut self, column: u16, row: u16) {
+        self.panes.click(column, row);
+    }
+
+    fn poll(&mut self) {
+        self.refresh();
+    }
+
+    fn busy(&self) -> bool {
+        self.panes.progress().is_some()
+    }
+
+    fn draw<B: Backend>(&mut self, frame: &mut Frame<B>) {
+        comparator_ui(frame, self)
+    }
+}
+
+impl<'a> Handler<'a> for Merge<'a> {
+    const COMPARE: bool = true;
+
+    fn focused_mut(&mut self) -> &mut Viewer<'a> {
+        self.panes.focused_mut()
+    }
+
+    fn version(&self) -> usize {
+        self.panes.version()
+    }
+
+    fn run(&mut self, input: Input, pending: &mut Pending) -> bool {
+        match input {
+            // Nothing to obtain from or put to with three panes
+            Input::Action(Action::Obtain | Action::Put, _) => true,
+            input => compare(&mut self.panes, input, pending),
+        }
+    }
+
+    fn update(&mut self) {
+        self.panes.sync();
+        self.refresh();
+    }
+
+    fn click(&mut self, column: u16, row: u16) {
+        self.panes.click(column, row);
+    }
+
+    fn poll(&mut self) {
+        self.refresh();
+    }
+
+    fn busy(&self) -> bool {
+        self.panes.progress().is_some()
+    }
+
+    fn draw<B: Backend>(&mut self, frame: &mut Frame<B>) {
+        merge_ui(frame, self)
+    }
+}
+
+/// Plays `keys` `count` times as if they were typed, returns false to quit
+fn play<'a, H: Handler<'a>>(
+    handler: &mut H,
+    keys: &[Key],
+    count: usize,
+    pending: &mut Pending,
+) -> bool {
+    if pending.depth >= DEPTH {
+        return true;
+    }
+
+    pending.depth += 1;
+    let running = (0..count).all(|_| keys.iter().all(|key| press(handler, *key, pending)));
+    pending.depth -= 1;
+
+    running
+}
+
+/// Handles a key typed or played from a macro, returns false to quit
+fn press<'a, H: Handler<'a>>(handler: &mut H, key: Key, pending: &mut Pending) -> bool {
+    if pending.depth == 0 {
+        if let Some((_, keys)) = &mut pending.recording {
+            keys.push(key);
+        }
+    }
+    if !pending.repeating {
+        if pending.command.is_empty() {
+            pending.version = handler.version();
+            pending.skip = false;
+        }
+        pending.command.push(key);
+    }
+
+    let running = match pending.name.take() {
+        Some(name) => {
+            let register = key.char();
+            match (name, register) {
+                (Macro::Record, Some(c)) => {
+                    pending.recording = Some((c, vec![]));
+                    true
+                }
+                (Macro::Play(count), Some(c)) => {
+                    let c = if c == '@' { pending.played } else { Some(c) };
+                    match c.and_then(|c| Some((c, pending.macros.get(&c)?.clone()))) {
+                        Some((c, keys)) => {
+                            pending.played = Some(c);
+                            // The commands of the macro are repeated on their own
+                            pending.command.clear();
+                            play(handler, &keys, count, pending)
                         }
-                        (Mode::Normal, KeyCode::Char('o')) => {
-                            viewer.append();
-                            viewer.mode = Mode::Insert;
-                            viewer.right();
+                        None => {
+                            let c = c.unwrap_or('@');
+                            handler.focused_mut().status = Some(format!("no macro in @{c}"));
+                            true
                         }
-                        (Mode::Normal, KeyCode::Char('v')) => viewer.mode = Mode::Visual,
-                        (Mode::Normal | Mode::Visual, KeyCode::Char('H')) => {
-                            viewer.highlight();
-                            viewer.mode = Mode::Normal;
+                    }
+                }
+                (_, None) => true,
+            }
+        }
+        None => {
+            let inputs = handle(handler.focused_mut(), key, pending, H::COMPARE);
+            inputs.into_iter().all(|input| match input {
+                Input::Action(Action::Record, _) => {
+                    match pending.recording.take() {
+                        Some((register, mut keys)) => {
+                            // Without the key stopping the recording
+                            keys.pop();
+                            pending.macros.insert(register, keys);
                         }
-                        (Mode::Normal, KeyCode::Char('g')) => viewer.selection.set(0),
-                        (Mode::Normal, KeyCode::Char('G')) => {
-                            viewer.selection.set(viewer.data.len() - 1)
+                        None => {
+                            pending.name = Some(Macro::Record);
+                            handler.focused_mut().status = Some(
+                                "type a register to record into, :q or Ctrl-q quits".to_string(),
+                            );
                         }
-                        (Mode::Normal, KeyCode::Char('0')) => viewer
-                            .selection
-                            .set(viewer.selection.start - viewer.selection.start % 16),
-                        (Mode::Visual, KeyCode::Char('y')) => viewer.yank(),
-                        (_, KeyCode::Esc) => viewer.mode = Mode::Normal,
-                        (Mode::Insert, KeyCode::Char(c)) => match (input, c.to_digit(16)) {
-                            (None, Some(b)) => input = Some(b),
-                            (Some(a), Some(b)) => {
-                                viewer.set(Some((a * 16 + b) as u8));
-                                viewer.right();
-                                input = None;
-                            }
-                            _ => {}
-                        },
-                        _ => {}
                     }
+                    true
                 }
-            }
+                Input::Action(Action::Play, count) => {
+                    pending.name = Some(Macro::Play(count));
+                    true
+                }
+                Input::Action(Action::Repeat, count) => {
+                    pending.skip = true;
+                    if pending.repeating {
+                        return true;
+                    }
+                    let keys = pending.edit.clone();
+                    pending.repeating = true;
+                    let running = play(handler, &keys, count, pending);
+                    pending.repeating = false;
+                    running
+                }
+                input => {
+                    if let Input::Action(Action::Undo | Action::Redo, _) = input {
+                        pending.skip = true;
+                    }
+                    handler.run(input, pending)
+                }
+            })
         }
-        if last_tick.elapsed() >= tick_rate {
-            last_tick = Instant::now();
+    };
+    if !running {
+        return false;
+    }
+
+    handler.update();
+
+    // The command is over once back in normal mode with nothing left to type
+    let viewer = handler.focused_mut();
+    let done = viewer.mode == Mode::Normal
+        && pending.parser.idle()
+        && pending.name.is_none()
+        && !pending.register;
+    if done && !pending.repeating {
+        if !pending.command.is_empty() && !pending.skip && handler.version() != pending.version {
+            pending.edit = std::mem::take(&mut pending.command);
         }
+        pending.command.clear();
     }
+
+    if let (0, Some((register, _))) = (pending.depth, &pending.recording) {
+        let viewer = handler.focused_mut();
+        if viewer.status.is_none() {
+            viewer.status = Some(format!("recording @{register}"));
+        }
+    }
+
+    true
 }
 
-fn run_comparator<B: Backend>(
+/// Shown until the first key, as `w` used to save and `q` to quit
+const HINT: &str =
+    "w moves by words and q records macros, save with :w or Ctrl-s, quit with :q or Ctrl-q";
+
+fn run<'a, B: Backend, H: Handler<'a>>(
     terminal: &mut Terminal<B>,
-    mut comparator: Comparator,
+    mut handler: H,
     tick_rate: Duration,
 ) -> std::io::Result<()> {
     let mut last_tick = Instant::now();
 
+    let mut pending = Pending::default();
+    handler.focused_mut().status = Some(HINT.to_string());
+
     loop {
-        terminal.draw(|f| comparator_ui(f, &mut comparator))?;
+        handler.poll();
+        terminal.draw(|f| handler.draw(f))?;
 
-        let timeout = tick_rate
+        let mut timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
+        if handler.busy() {
+            timeout = std::cmp::min(timeout, Duration::from_millis(50));
+        }
         if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') => return Ok(()),
-                        KeyCode::Char('h') => comparator.left(),
-                        KeyCode::Char('j') => comparator.down(),
-                        KeyCode::Char('k') => comparator.up(),
-                        KeyCode::Char('l') => comparator.right(),
-                        _ => {}
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    handler.focused_mut().status = None;
+
+                    if !press(&mut handler, Key::from(key), &mut pending) {
+                        return Ok(());
                     }
                 }
+                Event::Mouse(event) => {
+                    // A click focuses the pane under it
+                    if let MouseEventKind::Down(_) = event.kind {
+                        handler.click(event.column, event.row);
+                    }
+
+                    if mouse(handler.focused_mut(), event, &mut pending) {
+                        handler.update();
+                    }
+                }
+                _ => {}
             }
         }
         if last_tick.elapsed() >= tick_rate {
@@ -130,53 +846,240 @@ fn run_comparator<B: Backend>(
     }
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a hex dump without starting the TUI
+    Dump(dump::Options),
+    /// Print the differences between two files, exits with 1 if they differ
+    Diff(diff::Options),
+    /// Create and apply IPS, BPS and hex patches
+    #[command(subcommand)]
+    Patch(patch::Command),
+}
+
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// File to open, `-` reads from stdin
     file: Option<String>,
     other: Option<String>,
+    /// Compare three files, `file` being the common base of `other` and this one
+    #[arg(requires = "other")]
+    third: Option<String>,
+    /// Write the buffer to stdout on exit
+    #[arg(short = 'o', long)]
+    stdout: bool,
+    /// Preview an IPS, BPS or hex patch on the file, `A` applies it
+    #[arg(short, long, conflicts_with = "other")]
+    patch: Option<String>,
+    /// Diff algorithm used when comparing two files
+    #[arg(short, long, value_enum, default_value_t = Algorithm::Myers)]
+    algorithm: Algorithm,
+    #[command(flatten)]
+    ignore: ignore::Options,
+    /// Config file, `~/.config/lazyhex/config.toml` by default
+    #[arg(short, long)]
+    config: Option<String>,
+    /// Theme replacing the one of the config file: dark, light, high-contrast
+    /// or one defined in the config file
+    #[arg(short, long)]
+    theme: Option<String>,
+    /// Run a Rhai script on the file without starting the TUI, then save it
+    /// or write it to stdout with `-o`
+    #[arg(short, long, conflicts_with_all = ["other", "patch"])]
+    script: Option<String>,
+}
+
+fn number(s: &str) -> Result<usize, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+
+    parsed.map_err(|err| format!("invalid number {s:?}: {err}"))
+}
+
+fn load(path: &str) -> std::io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut data = vec![];
+        std::io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+/// Puts `path` in front of the errors about it, as the subcommands print them
+fn context(path: &str) -> impl FnOnce(std::io::Error) -> std::io::Error + '_ {
+    move |err| std::io::Error::new(err.kind(), format!("{path}: {err}"))
+}
+
+/// Bytes of `path`, none for a new file
+fn open(path: &str) -> std::io::Result<Vec<u8>> {
+    match load(path) {
+        Err(err) if path == "-" => Err(err),
+        result => Ok(result.unwrap_or_default()),
+    }
+}
+
+/// Bytes of `path`, a single zero for an empty or new file as the viewer
+/// always shows a byte
+fn read(path: &str) -> std::io::Result<Vec<u8>> {
+    let data = open(path)?;
+    Ok(if data.is_empty() { vec![0] } else { data })
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    if let Some(command) = args.command {
+        match command {
+            Command::Dump(options) => {
+                if let Err(err) = dump::dump(options) {
+                    eprintln!("lazyhex: {err}");
+                    std::process::exit(2);
+                }
+            }
+            // 1 when the files differ like cmp and diff, 2 on errors
+            Command::Diff(options) => match diff::diff(options) {
+                Ok(true) => std::process::exit(1),
+                Ok(false) => {}
+                Err(err) => {
+                    eprintln!("lazyhex: {err}");
+                    std::process::exit(2);
+                }
+            },
+            Command::Patch(command) => {
+                if let Err(err) = patch::patch(command) {
+                    eprintln!("lazyhex: {err}");
+                    std::process::exit(2);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    let config = match Config::load(args.config.as_deref(), args.theme.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("lazyhex: {err}");
+            std::process::exit(2);
+        }
+    };
+
+    if args.file.is_none() && !std::io::stdin().is_terminal() {
+        args.file = Some("-".to_string());
+    }
+
+    // Scripts run without the viewer, on the bytes as they are
+    let mut data = match (&args.file, &args.script) {
+        (Some(f), Some(_)) => open(f)?,
+        (None, Some(_)) => vec![],
+        (Some(f), None) => read(f)?,
+        (None, None) => vec![0],
+    };
+    let masks = args.ignore.masks()?;
+    if !masks.is_empty() && args.other.is_none() {
+        eprintln!("lazyhex: ignored regions need two or three files to compare");
+        std::process::exit(2);
+    }
+    let edits = match &args.patch {
+        Some(patch) => Some(patch::edits(&std::fs::read(patch)?, &data)?),
+        None => None,
+    };
+    let mut other = match &args.other {
+        Some(f) => Some(read(f)?),
+        None => None,
+    };
+    let mut third = match &args.third {
+        Some(f) => Some(read(f)?),
+        None => None,
+    };
+
+    if let Some(script) = &args.script {
+        let filename = args.file.as_deref().filter(|f| *f != "-");
+        let mut viewer = Viewer::new(&mut data, filename, &config);
+        let output = match script::run(&mut viewer, script, false) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("lazyhex: {err}");
+                std::process::exit(1);
+            }
+        };
+        let edited = viewer.edited;
+        // Unlike the viewer, files can be empty
+        if output.empty {
+            data.clear();
+        }
+
+        // Printed lines go to stderr when stdout is taken by the buffer
+        for line in output.printed {
+            if args.stdout {
+                eprintln!("{line}");
+            } else {
+                println!("{line}");
+            }
+        }
+        if args.stdout {
+            std::io::stdout().write_all(&data)?;
+        } else if let (Some(path), true) = (filename, edited) {
+            std::fs::write(path, &data)?;
+        }
+        return Ok(());
+    }
+
+    // Keep stdout free for the buffer when it is piped, the TUI goes to the terminal
+    let output: Box<dyn Write> = if std::io::stdout().is_terminal() {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(OpenOptions::new().write(true).open("/dev/tty")?)
+    };
 
     enable_raw_mode()?;
-    let mut stdout = std::io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    let backend = CrosstermBackend::new(output);
     let mut terminal = Terminal::new(backend)?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableMouseCapture
+    )?;
 
     let tick_rate = Duration::from_millis(250);
 
-    let res = match (&args.file, &args.other) {
-        (None, None) => {
-            let mut data = vec![Some(0)];
-            let viewer = Viewer::new(&mut data, args.file.as_deref());
-            run_viewer(&mut terminal, viewer, tick_rate)
-        }
-        (Some(f), None) => {
-            let mut data = std::fs::read(f)
-                .unwrap_or(vec![0])
-                .into_iter()
-                .map(|n| Some(n))
-                .collect();
-            let viewer = Viewer::new(&mut data, args.file.as_deref());
-            run_viewer(&mut terminal, viewer, tick_rate)
-        }
-        (Some(a), Some(b)) => {
-            let mut adata = std::fs::read(a)
-                .unwrap_or(vec![0])
-                .into_iter()
-                .map(|n| Some(n))
-                .collect();
-            let mut bdata = std::fs::read(b)
-                .unwrap_or(vec![0])
-                .into_iter()
-                .map(|n| Some(n))
-                .collect();
-            let comparator = Comparator::new(&mut adata, &mut bdata, a, b);
-            run_comparator(&mut terminal, comparator, tick_rate)
-        }
-        (None, Some(_)) => unreachable!(),
+    let res = match (&args.file, &mut other, &mut third) {
+        (file, None, _) => {
+            let filename = file.as_deref().filter(|f| *f != "-");
+            let mut viewer = Viewer::new(&mut data, filename, &config);
+            if let Some(edits) = edits {
+                viewer.preview(edits);
+            }
+            run(&mut terminal, viewer, tick_rate)
+        }
+        (Some(a), Some(bdata), None) => {
+            let files = [Some(a.as_str()), args.other.as_deref()];
+            let [a, b] = files.map(|f| f.filter(|f| *f != "-"));
+            let comparator =
+                Comparator::new(&mut data, bdata, a, b, args.algorithm, masks, &config);
+            run(&mut terminal, comparator, tick_rate)
+        }
+        (Some(a), Some(bdata), Some(cdata)) => {
+            let files = [
+                Some(a.as_str()),
+                args.other.as_deref(),
+                args.third.as_deref(),
+            ];
+            let merge = Merge::new(
+                [&mut data, bdata, cdata],
+                files.map(|f| f.filter(|f| *f != "-")),
+                args.algorithm,
+                masks,
+                &config,
+            );
+            run(&mut terminal, merge, tick_rate)
+        }
+        (None, Some(_), _) => unreachable!(),
     };
 
     disable_raw_mode()?;
@@ -191,5 +1094,50 @@ fn main() -> Result<(), Box<dyn Error>> {
         println!("{err:?}");
     }
 
+    if args.stdout && other.is_none() {
+        std::io::stdout().write_all(&data)?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes 0 to 7 after typing `keys` in a viewer
+    fn typed(keys: &[&str]) -> Vec<u8> {
+        let config = Config::default();
+        let mut data: Vec<u8> = (0..8).collect();
+        let mut viewer = Viewer::new(&mut data, None, &config);
+        let mut pending = Pending::default();
+        for key in keys.iter().flat_map(|keys| keymap::parse(keys).unwrap()) {
+            assert!(press(&mut viewer, key, &mut pending), "{keys:?}");
+        }
+        data
+    }
+
+    #[test]
+    fn macros() {
+        // Recorded without the q stopping it
+        assert_eq!(typed(&["qa<C-a>lq"]), [1, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["qa<C-a>lq", "2@a"]), [1, 2, 3, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["qa<C-a>lq", "@a", "@@"]), [1, 2, 3, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["qa<C-a>lq", "@b"]), [1, 1, 2, 3, 4, 5, 6, 7]);
+        // Stops at the depth limit instead of overflowing the stack
+        assert_eq!(typed(&["qaxq", "qb@bq", "@b"]), [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(typed(&["x", "."]), [2, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["2x", "."]), [4, 5, 6, 7]);
+        assert_eq!(typed(&["x", "2."]), [3, 4, 5, 6, 7]);
+        // Motions, undos and repeats are not repeated themselves
+        assert_eq!(typed(&["<C-a>", "l", "."]), [1, 2, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["x", "x", "u", "."]), [2, 3, 4, 5, 6, 7]);
+        assert_eq!(typed(&["x", ".", "."]), [3, 4, 5, 6, 7]);
+        // A macro repeats its last command rather than the whole macro
+        assert_eq!(typed(&["qaxlq", "@a", "."]), [1, 3, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{ops::Range, thread};
+
+use crate::{
+    comparator::{hunks, Algorithm, Hunk},
+    config::{Config, Pair, Theme},
+    ignore::{self, Mask},
+    panes::{Job, Panes},
+    viewer::{Highlight, Viewer},
+};
+
+/// Three-way comparison of a common base with two modified versions
+pub struct Merge<'a> {
+    pub panes: Panes<'a, Vec<Chunk>, 3>,
+    pub chunks: Vec<Chunk>,
+    /// Regions compared as equal whatever their content
+    pub masks: Vec<Mask>,
+    pub config: &'a Config,
+}
+
+/// Indexes of the panes
+pub const BASE: usize = 0;
+pub const OURS: usize = 1;
+pub const THEIRS: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change {
+    /// Only ours differs from the base
+    Ours,
+    /// Only theirs differs from the base
+    Theirs,
+    /// Both sides made the same change
+    Both,
+    /// Both sides changed the same bytes differently
+    Conflict,
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Ours => "ours",
+            Change::Theirs => "theirs",
+            Change::Both => "both",
+            Change::Conflict => "conflict",
+        }
+    }
+
+    pub fn color(&self, theme: &Theme) -> Pair {
+        match self {
+            Change::Ours | Change::Theirs => theme.replaced,
+            Change::Both => theme.added,
+            Change::Conflict => theme.conflict,
+        }
+    }
+}
+
+/// A region changed by at least one side, ranges are offsets in the original files
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    pub change: Change,
+    pub base: Range<usize>,
+    pub ours: Range<usize>,
+    pub theirs: Range<usize>,
+}
+
+/// Groups the hunks of both sides that touch the same bytes of the base
+pub fn chunks(
+    ours: &[u8],
+    theirs: &[u8],
+    hunks_ours: &[Hunk],
+    hunks_theirs: &[Hunk],
+) -> Vec<Chunk> {
+    let mut chunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    // Length difference between each side and the base before the current chunk
+    let (mut delta_ours, mut delta_theirs) = (0isize, 0isize);
+
+    while i < hunks_ours.len() || j < hunks_theirs.len() {
+        let start = match (hunks_ours.get(i), hunks_theirs.get(j)) {
+            (Some(a), Some(b)) => std::cmp::min(a.old.start, b.old.start),
+            (Some(a), None) => a.old.start,
+            (None, Some(b)) => b.old.start,
+            (None, None) => unreachable!(),
+        };
+
+        let (first_ours, first_theirs) = (i, j);
+        let mut end = start;
+        loop {
+            let before = (i, j);
+            while let Some(hunk) = hunks_ours.get(i).filter(|h| h.old.start <= end) {
+                end = std::cmp::max(end, hunk.old.end);
+                i += 1;
+            }
+            while let Some(hunk) = hunks_theirs.get(j).filter(|h| h.old.start <= end) {
+                end = std::cmp::max(end, hunk.old.end);
+                j += 1;
+            }
+            if (i, j) == before {
+                break;
+            }
+        }
+
+        let shift = |position: usize, delta: isize| position.checked_add_signed(delta).unwrap();
+        let growth = |hunks: &[Hunk]| {
+            hunks
+                .iter()
+                .map(|h| h.new.len() as isize - h.old.len() as isize)
+                .sum::<isize>()
+        };
+
+        let ours_start = shift(start, delta_ours);
+        let theirs_start = shift(start, delta_theirs);
+        delta_ours += growth(&hunks_ours[first_ours..i]);
+        delta_theirs += growth(&hunks_theirs[first_theirs..j]);
+        let ours_range = ours_start..shift(end, delta_ours);
+        let theirs_range = theirs_start..shift(end, delta_theirs);
+
+        let change = if first_theirs == j {
+            Change::Ours
+        } else if first_ours == i {
+            Change::Theirs
+        } else if ours[ours_range.clone()] == theirs[theirs_range.clone()] {
+            Change::Both
+        } else {
+            Change::Conflict
+        };
+
+        chunks.push(Chunk {
+            change,
+            base: start..end,
+            ours: ours_range,
+            theirs: theirs_range,
+        });
+    }
+
+    chunks
+}
+
+/// Number of chunks changed by ours, theirs, both sides and in conflict
+pub fn count(chunks: &[Chunk]) -> (usize, usize, usize, usize) {
+    chunks
+        .iter()
+        .fold((0, 0, 0, 0), |(o, t, b, c), chunk| match chunk.change {
+            Change::Ours => (o + 1, t, b, c),
+            Change::Theirs => (o, t + 1, b, c),
+            Change::Both => (o, t, b + 1, c),
+            Change::Conflict => (o, t, b, c + 1),
+        })
+}
+
+impl<'a> Merge<'a> {
+    pub fn new(
+        data: [&'a mut Vec<u8>; 3],
+        files: [Option<&'a str>; 3],
+        algorithm: Algorithm,
+        masks: Vec<Mask>,
+        config: &'a Config,
+    ) -> Self {
+        let [data_base, data_ours, data_theirs] = data;
+        let [file_base, file_ours, file_theirs] = files;
+
+        let viewers = [
+            Viewer::new(data_base, file_base, config),
+            Viewer::new(data_ours, file_ours, config),
+            Viewer::new(data_theirs, file_theirs, config),
+        ];
+        let mut merge = Self {
+            panes: Panes::new(viewers, OURS, algorithm),
+            chunks: vec![],
+            masks,
+            config,
+        };
+
+        merge.compute();
+        merge
+    }
+
+    /// Recomputes the chunks from scratch in the background, removing the
+    /// previous alignment until the new one is ready
+    pub fn compute(&mut self) {
+        let [base, ours, theirs] = self.panes.viewers.each_ref().map(|v| {
+            let mut data = v.data.clone();
+            ignore::clear(&self.masks, &mut data);
+            data
+        });
+        let algorithm = self.panes.algorithm;
+
+        let job = Job::spawn(2, move |progress| {
+            let (hunks_ours, hunks_theirs) = thread::scope(|s| {
+                let ours = s.spawn(|| hunks(&base, &ours, algorithm, &progress[0]));
+                let theirs = hunks(&base, &theirs, algorithm, &progress[1]);
+                (ours.join().unwrap(), theirs)
+            });
+            if progress.iter().any(|p| p.cancelled()) {
+                return None;
+            }
+            Some(chunks(&ours, &theirs, &hunks_ours, &hunks_theirs))
+        });
+        if self.panes.start(job) {
+            self.chunks.clear();
+        }
+    }
+
+    /// Recomputes the chunks once a pane was edited or the algorithm changed,
+    /// aligns the buffers once they are done
+    pub fn refresh(&mut self) {
+        if self.panes.outdated() {
+            self.compute();
+        }
+        if let Some(chunks) = self.panes.poll() {
+            self.align(chunks);
+        }
+    }
+
+    fn align(&mut self, chunks: Vec<Chunk>) {
+        // Gap bytes inserted so far in each pane
+        let mut offsets = [0; 3];
+        let mut aligned = vec![];
+
+        for chunk in chunks.iter() {
+            let ranges = [&chunk.base, &chunk.ours, &chunk.theirs];
+            let width = ranges.iter().map(|r| r.len()).max().unwrap();
+            let start = chunk.base.start + offsets[0];
+            aligned.push(start..start + width);
+
+            let painted = match chunk.change {
+                Change::Ours => [true, true, false],
+                Change::Theirs => [true, false, true],
+                Change::Both | Change::Conflict => [true, true, true],
+            };
+            let Pair { bg, fg } = chunk.change.color(&self.config.theme);
+
+            for (n, viewer) in self.panes.viewers.iter_mut().enumerate() {
+                let range = ranges[n];
+                if painted[n] && !range.is_empty() {
+                    viewer.highlights.push(Highlight {
+                        start: range.start + offsets[n],
+                        end: range.end + offsets[n] - 1,
+                        bg,
+                        fg,
+                    });
+                }
+
+                let gap = range.end + offsets[n];
+                viewer.gaps.push(gap..gap + width - range.len());
+                offsets[n] += width - range.len();
+            }
+        }
+
+        // Once the gaps are known
+        self.panes.dim(&self.masks, self.config.theme.ignored);
+
+        self.chunks = chunks;
+        self.panes.ranges = aligned;
+        self.panes.aligned();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comparator::Progress;
+
+    fn merge(base: &[u8], ours: &[u8], theirs: &[u8]) -> Vec<(Change, [Range<usize>; 3])> {
+        let diff = |side| hunks(base, side, Algorithm::Myers, &Progress::default());
+        chunks(ours, theirs, &diff(ours), &diff(theirs))
+            .into_iter()
+            .map(|c| (c.change, [c.base, c.ours, c.theirs]))
+            .collect()
+    }
+
+    #[test]
+    fn one_side() {
+        assert_eq!(
+            merge(b"abcdef", b"abXdef", b"abcdef"),
+            [(Change::Ours, [2..3, 2..3, 2..3])]
+        );
+        assert_eq!(
+            merge(b"abcdef", b"abcdef", b"abcdYYef"),
+            [(Change::Theirs, [4..4, 4..4, 4..6])]
+        );
+    }
+
+    #[test]
+    fn both_sides() {
+        // Apart, the offsets of the later chunk moved by the earlier one
+        assert_eq!(
+            merge(b"abcdefgh", b"aXXbcdefgh", b"abcdefgY"),
+            [
+                (Change::Ours, [1..1, 1..3, 1..1]),
+                (Change::Theirs, [7..8, 9..10, 7..8]),
+            ]
+        );
+        assert_eq!(
+            merge(b"abcdef", b"abXYef", b"abXYef"),
+            [(Change::Both, [2..4, 2..4, 2..4])]
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        assert_eq!(
+            merge(b"abcdef", b"abXdef", b"abYdef"),
+            [(Change::Conflict, [2..3, 2..3, 2..3])]
+        );
+        // Overlapping changes are a single chunk, deleted on one side
+        assert_eq!(
+            merge(b"abcdef", b"aXcdef", b"abf"),
+            [(Change::Conflict, [1..5, 1..5, 1..2])]
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::{collections::hash_map::RandomState, hash::BuildHasher};
+
+/// An operation rewriting the selected bytes, keys repeat over the selection
+pub enum Operation {
+    Fill(Vec<u8>),
+    Random,
+    /// Adds each byte of the key with wrapping, increments and decrements
+    /// add a single byte
+    Add(Vec<u8>),
+    Xor(Vec<u8>),
+    And(Vec<u8>),
+    Or(Vec<u8>),
+    /// Reverses the bytes of each word of this many bytes
+    Swap(usize),
+    Reverse,
+    ShiftLeft(u32),
+    ShiftRight(u32),
+    RotateLeft(u32),
+    RotateRight(u32),
+}
+
+/// Bytes written in hex like `ff`, `0xff`, `de ad` or `deadbeef`
+fn bytes(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+
+    for token in s.split_whitespace() {
+        let digits = token.strip_prefix("0x").unwrap_or(token);
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid bytes {token:?}, expected hex"));
+        }
+        let pairs: Vec<_> = match digits.len() {
+            1 | 2 => vec![digits],
+            n if n % 2 == 0 => (0..n).step_by(2).map(|i| &digits[i..i + 2]).collect(),
+            _ => {
+                return Err(format!(
+                    "invalid bytes {token:?}, expected whole bytes in hex"
+                ))
+            }
+        };
+        for pair in pairs {
+            let byte = u8::from_str_radix(pair, 16)
+                .map_err(|_| format!("invalid bytes {token:?}, expected hex"))?;
+            bytes.push(byte);
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err("expected bytes in hex".to_string());
+    }
+
+    Ok(bytes)
+}
+
+fn bits(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(bits) if bits < 8 => Ok(bits),
+        _ => Err(format!("invalid shift {s:?}, expected 0 to 7 bits")),
+    }
+}
+
+/// Applies `f` to each byte with the byte of the key at the same place
+fn with_key(bytes: &mut [u8], key: &[u8], f: impl Fn(u8, u8) -> u8) {
+    for (byte, k) in bytes.iter_mut().zip(key.iter().cycle()) {
+        *byte = f(*byte, *k);
+    }
+}
+
+impl Operation {
+    /// Operation of a command typed after `:`, None if it names none
+    pub fn parse(name: &str, argument: &str) -> Option<Result<Self, String>> {
+        let count = || match argument {
+            "" => Ok(1),
+            _ => crate::number(argument).and_then(|n| {
+                u8::try_from(n)
+                    .map_err(|_| format!("invalid count {argument:?}, expected 0 to 255"))
+            }),
+        };
+
+        let operation = match name {
+            "fill" => bytes(argument).map(Operation::Fill),
+            "random" => Ok(Operation::Random),
+            "inc" => count().map(|n| Operation::Add(vec![n])),
+            "dec" => count().map(|n| Operation::Add(vec![n.wrapping_neg()])),
+            "add" => bytes(argument).map(Operation::Add),
+            "xor" => bytes(argument).map(Operation::Xor),
+            "and" => bytes(argument).map(Operation::And),
+            "or" => bytes(argument).map(Operation::Or),
+            "swap" => match argument {
+                "16" => Ok(Operation::Swap(2)),
+                "32" => Ok(Operation::Swap(4)),
+                "64" => Ok(Operation::Swap(8)),
+                _ => Err(format!(
+                    "invalid word size {argument:?}, expected 16, 32 or 64"
+                )),
+            },
+            "reverse" => Ok(Operation::Reverse),
+            "shl" => bits(argument).map(Operation::ShiftLeft),
+            "shr" => bits(argument).map(Operation::ShiftRight),
+            "rol" => bits(argument).map(Operation::RotateLeft),
+            "ror" => bits(argument).map(Operation::RotateRight),
+            _ => return None,
+        };
+
+        Some(operation)
+    }
+
+    pub fn apply(&self, bytes: &mut [u8]) {
+        match self {
+            Operation::Fill(pattern) => with_key(bytes, pattern, |_, k| k),
+            Operation::Random => {
+                // xorshift seeded by the random keys of the hash maps
+                let mut state = RandomState::new().hash_one(bytes.len()) | 1;
+                for byte in bytes.iter_mut() {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    *byte = (state >> 32) as u8;
+                }
+            }
+            Operation::Add(key) => with_key(bytes, key, u8::wrapping_add),
+            Operation::Xor(key) => with_key(bytes, key, |b, k| b ^ k),
+            Operation::And(key) => with_key(bytes, key, |b, k| b & k),
+            Operation::Or(key) => with_key(bytes, key, |b, k| b | k),
+            // A last partial word is left as it is
+            Operation::Swap(size) => bytes
+                .chunks_exact_mut(*size)
+                .for_each(|word| word.reverse()),
+            Operation::Reverse => bytes.reverse(),
+            Operation::ShiftLeft(n) => bytes.iter_mut().for_each(|b| *b <<= n),
+            Operation::ShiftRight(n) => bytes.iter_mut().for_each(|b| *b >>= n),
+            Operation::RotateLeft(n) => bytes.iter_mut().for_each(|b| *b = b.rotate_left(*n)),
+            Operation::RotateRight(n) => bytes.iter_mut().for_each(|b| *b = b.rotate_right(*n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn applied(name: &str, argument: &str, bytes: &[u8]) -> Vec<u8> {
+        let Some(Ok(operation)) = Operation::parse(name, argument) else {
+            panic!("{name} {argument}");
+        };
+        let mut bytes = bytes.to_vec();
+        operation.apply(&mut bytes);
+        bytes
+    }
+
+    fn error(name: &str, argument: &str) -> String {
+        match Operation::parse(name, argument) {
+            Some(Err(err)) => err,
+            _ => panic!("{name} {argument}"),
+        }
+    }
+
+    #[test]
+    fn wrap_around() {
+        assert_eq!(applied("inc", "", &[0x00, 0xff]), [0x01, 0x00]);
+        assert_eq!(applied("inc", "0x10", &[0xf8]), [0x08]);
+        assert_eq!(applied("inc", "255", &[0x02]), [0x01]);
+        assert_eq!(applied("dec", "", &[0x00, 0x80]), [0xff, 0x7f]);
+        assert_eq!(applied("dec", "0", &[0x42]), [0x42]);
+        assert_eq!(
+            applied("add", "01 ff", &[0xff, 0x01, 0x10]),
+            [0x00, 0x00, 0x11]
+        );
+        assert_eq!(applied("shl", "1", &[0x81]), [0x02]);
+        assert_eq!(applied("rol", "1", &[0x81]), [0x03]);
+        assert_eq!(applied("ror", "1", &[0x81]), [0xc0]);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            applied("fill", "de ad", &[0; 5]),
+            [0xde, 0xad, 0xde, 0xad, 0xde]
+        );
+        assert_eq!(applied("fill", "0xdead", &[0; 3]), [0xde, 0xad, 0xde]);
+        assert_eq!(applied("xor", "ff", &[0x0f, 0xf0]), [0xf0, 0x0f]);
+        assert_eq!(applied("and", "f0 0f", &[0xff, 0xff]), [0xf0, 0x0f]);
+        // A last partial word is left as it is
+        assert_eq!(
+            applied("swap", "32", &[1, 2, 3, 4, 5, 6]),
+            [4, 3, 2, 1, 5, 6]
+        );
+        assert_eq!(applied("reverse", "", &[1, 2, 3]), [3, 2, 1]);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Operation::parse("nop", "").is_none());
+        assert_eq!(
+            error("inc", "256"),
+            "invalid count \"256\", expected 0 to 255"
+        );
+        assert_eq!(
+            error("dec", "-1"),
+            "invalid number \"-1\": invalid digit found in string"
+        );
+        assert_eq!(error("fill", ""), "expected bytes in hex");
+        assert_eq!(error("fill", "zz"), "invalid bytes \"zz\", expected hex");
+        assert_eq!(
+            error("xor", "0xfff"),
+            "invalid bytes \"0xfff\", expected whole bytes in hex"
+        );
+        assert_eq!(
+            error("swap", "24"),
+            "invalid word size \"24\", expected 16, 32 or 64"
+        );
+        assert_eq!(
+            error("shl", "8"),
+            "invalid shift \"8\", expected 0 to 7 bits"
+        );
+    }
+}
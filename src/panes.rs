@@ -0,0 +1,254 @@
+use std::{
+    ops::Range,
+    sync::{
+        mpsc::{self, Receiver},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{
+    comparator::{Algorithm, Progress},
+    config::Pair,
+    ignore::Mask,
+    ui,
+    viewer::{Highlight, Viewer},
+};
+
+/// Work done on another thread, with one progress for each of its parts
+pub struct Job<T> {
+    progress: Vec<Arc<Progress>>,
+    receiver: Receiver<T>,
+}
+
+impl<T: Send + 'static> Job<T> {
+    /// Runs `work` in the background, nothing is received when it returns None
+    pub fn spawn<F>(parts: usize, work: F) -> Self
+    where
+        F: FnOnce(&[Arc<Progress>]) -> Option<T> + Send + 'static,
+    {
+        let progress: Vec<Arc<Progress>> = (0..parts).map(|_| Arc::default()).collect();
+        let (sender, receiver) = mpsc::channel();
+
+        let shared = progress.clone();
+        thread::spawn(move || {
+            if let Some(result) = work(&shared) {
+                let _ = sender.send(result);
+            }
+        });
+
+        Self { progress, receiver }
+    }
+
+    /// Mean progress of the parts in permille
+    pub fn progress(&self) -> usize {
+        let sum: usize = self.progress.iter().map(|p| p.get()).sum();
+        sum / self.progress.len().max(1)
+    }
+
+    pub fn cancel(&self) {
+        self.progress.iter().for_each(|p| p.cancel());
+    }
+
+    /// Result of the work once it is done
+    pub fn poll(&self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Buffers compared side by side, aligned by the result of a background job
+pub struct Panes<'a, T, const N: usize> {
+    pub viewers: [Viewer<'a>; N],
+    /// Index of the pane the keys go to
+    pub focus: usize,
+    /// Whether the panes scroll together
+    pub synced: bool,
+    pub algorithm: Algorithm,
+    /// Positions of the hunks in the aligned buffers
+    pub ranges: Vec<Range<usize>>,
+    /// Sum of the versions of the buffers and the algorithm of the last job
+    computed: (usize, Algorithm),
+    /// Lengths of the buffers the gaps were made for
+    lengths: [usize; N],
+    job: Option<Job<T>>,
+}
+
+impl<'a, T: Send + 'static, const N: usize> Panes<'a, T, N> {
+    pub fn new(viewers: [Viewer<'a>; N], focus: usize, algorithm: Algorithm) -> Self {
+        Self {
+            viewers,
+            focus,
+            synced: true,
+            algorithm,
+            ranges: vec![],
+            computed: (0, algorithm),
+            lengths: [0; N],
+            job: None,
+        }
+    }
+
+    pub fn focused(&self) -> &Viewer<'a> {
+        &self.viewers[self.focus]
+    }
+
+    pub fn focused_mut(&mut self) -> &mut Viewer<'a> {
+        &mut self.viewers[self.focus]
+    }
+
+    /// Sum of the versions of the buffers, changing with every edit
+    pub fn version(&self) -> usize {
+        self.viewers.iter().map(|v| v.version).sum()
+    }
+
+    pub fn switch(&mut self) {
+        self.focus = (self.focus + 1) % N;
+    }
+
+    /// Focuses the pane drawn at `column` and `row` of the terminal, if any
+    pub fn click(&mut self, column: u16, row: u16) {
+        if let Some(focus) = self
+            .viewers
+            .iter()
+            .position(|viewer| ui::target(viewer, column, row).is_some())
+        {
+            self.focus = focus;
+        }
+    }
+
+    pub fn toggle_sync(&mut self) {
+        self.synced = !self.synced;
+        self.sync();
+    }
+
+    /// Moves the other panes to the cursor and the view of the focused one when they are synced
+    pub fn sync(&mut self) {
+        if !self.synced {
+            return;
+        }
+
+        let (position, top) = (self.focused().selection.end, self.focused().top);
+        let focus = self.focus;
+        for (i, viewer) in self.viewers.iter_mut().enumerate() {
+            if i != focus {
+                viewer
+                    .selection
+                    .set(std::cmp::min(position, viewer.len() - 1));
+                viewer.top = top;
+            }
+        }
+    }
+
+    /// Puts the cursor of every pane at `position`
+    pub fn select(&mut self, position: usize) {
+        for viewer in self.viewers.iter_mut() {
+            viewer.selection.set(position);
+        }
+    }
+
+    /// Replaces the running job, returns whether the alignment of the previous
+    /// one was removed until the new one is done
+    ///
+    /// The gaps still line up the bytes while no buffer changed length, like
+    /// when typing in insert mode, they are then kept until the job is done.
+    pub fn start(&mut self, job: Job<T>) -> bool {
+        if let Some(previous) = self.job.replace(job) {
+            previous.cancel();
+        }
+
+        let lengths = self.viewers.each_ref().map(|v| v.data.len());
+        if lengths == self.lengths {
+            return false;
+        }
+        self.lengths = lengths;
+        self.clear();
+        self.ranges.clear();
+        true
+    }
+
+    /// Removes the gaps and the highlights, keeping the cursor on the same byte
+    fn clear(&mut self) {
+        let focused = self.focused();
+        let index = focused.gaps.index(focused.selection.start);
+
+        for viewer in self.viewers.iter_mut() {
+            viewer.gaps.clear();
+            viewer.highlights.clear();
+        }
+
+        let last = self.viewers.iter().map(|v| v.len()).min().unwrap() - 1;
+        self.select(std::cmp::min(index, last));
+    }
+
+    /// Progress in permille of the running job, if any
+    pub fn progress(&self) -> Option<usize> {
+        self.job.as_ref().map(|job| job.progress())
+    }
+
+    /// Result of the job once it is done, the previous alignment removed
+    pub fn poll(&mut self) -> Option<T> {
+        let result = self.job.as_ref()?.poll()?;
+        self.job = None;
+        self.clear();
+        Some(result)
+    }
+
+    /// Keeps the cursors on the same byte once the gaps of the result were added
+    pub fn aligned(&mut self) {
+        let focused = self.focused();
+        let position = focused.gaps.position(focused.selection.start);
+        self.select(position);
+    }
+
+    /// Whether a buffer was edited or the algorithm changed since the last check
+    pub fn outdated(&mut self) -> bool {
+        let computed = (self.version(), self.algorithm);
+        let outdated = computed != self.computed;
+        self.computed = computed;
+        outdated
+    }
+
+    /// Dims the regions of the masks, below the highlights of the hunks
+    pub fn dim(&mut self, masks: &[Mask], colors: Pair) {
+        for viewer in self.viewers.iter_mut() {
+            let length = viewer.data.len();
+            let dimmed: Vec<_> = masks
+                .iter()
+                .flat_map(|mask| mask.ranges())
+                .filter(|range| range.start < length)
+                .map(|range| Highlight {
+                    start: viewer.gaps.position(range.start),
+                    end: viewer.gaps.position(std::cmp::min(range.end, length) - 1),
+                    bg: colors.bg,
+                    fg: colors.fg,
+                })
+                .collect();
+            viewer.highlights.splice(0..0, dimmed);
+        }
+    }
+
+    /// Index of the hunk under the cursor, or of the last one before it
+    pub fn hunk(&self) -> Option<usize> {
+        let position = self.focused().selection.start;
+        self.ranges.iter().rposition(|r| r.start <= position)
+    }
+
+    /// Moves to the `count`th hunk after the cursor, or the last one
+    pub fn next_hunk(&mut self, count: usize) {
+        let position = self.focused().selection.start;
+        let after = self.ranges.iter().filter(|r| r.start > position);
+        if let Some(start) = after.take(count).last().map(|r| r.start) {
+            self.focused_mut().selection.set(start);
+            self.sync();
+        }
+    }
+
+    /// Moves to the `count`th hunk before the cursor, or the first one
+    pub fn prev_hunk(&mut self, count: usize) {
+        let position = self.focused().selection.start;
+        let before = self.ranges.iter().rev().filter(|r| r.start < position);
+        if let Some(start) = before.take(count).last().map(|r| r.start) {
+            self.focused_mut().selection.set(start);
+            self.sync();
+        }
+    }
+}
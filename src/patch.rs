@@ -0,0 +1,601 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
+use clap::{Subcommand, ValueEnum};
+
+use crate::comparator::{hunks, Algorithm, Hunk, Progress};
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Positional writes, files up to 16MiB, no checksum
+    Ips,
+    /// Checksums of the original file, the result and the patch
+    Bps,
+    /// Same text as `lazyhex diff`, checked against the original bytes
+    Hex,
+}
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+/// Offsets are stored on 3 bytes
+const IPS_LIMIT: usize = 1 << 24;
+/// A record can't start here, its offset would be read as the end of the patch
+const IPS_EOF_OFFSET: usize = 0x454f46;
+
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Checksums of the source, the target and the patch
+const BPS_FOOTER: usize = 12;
+
+impl Format {
+    /// Format of a patch written to `path`, from its extension
+    pub fn from_path(path: &str) -> Self {
+        let extension = path.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ips") => Format::Ips,
+            Some("bps") => Format::Bps,
+            _ => Format::Hex,
+        }
+    }
+
+    /// Guesses the format of a patch from its header
+    pub fn detect(patch: &[u8]) -> Self {
+        if patch.starts_with(IPS_MAGIC) {
+            Format::Ips
+        } else if patch.starts_with(BPS_MAGIC) {
+            Format::Bps
+        } else {
+            Format::Hex
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Write the differences between two files as a patch
+    Create(CreateOptions),
+    /// Apply a patch to a file
+    Apply(ApplyOptions),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CreateOptions {
+    /// Original file
+    old: String,
+    /// Modified file
+    new: String,
+    #[arg(short, long, value_enum, default_value_t = Format::Hex)]
+    format: Format,
+    /// Diff algorithm, IPS patches are always positional
+    #[arg(short, long, value_enum, default_value_t = Algorithm::Myers)]
+    algorithm: Algorithm,
+    /// File to write the patch to, defaults to stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ApplyOptions {
+    /// Patch in any of the supported formats
+    patch: String,
+    /// File to patch, `-` reads from stdin
+    file: String,
+    /// File to write the result to, defaults to stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn truncated() -> io::Error {
+    invalid("truncated patch")
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(length).ok_or_else(truncated)?;
+        let bytes = self.bytes.get(self.position..end).ok_or_else(truncated)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Big endian number on `length` bytes
+    fn number(&mut self, length: usize) -> io::Result<usize> {
+        let bytes = self.take(length)?;
+        Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as usize))
+    }
+
+    /// Variable length number used by BPS
+    fn varint(&mut self) -> io::Result<usize> {
+        let overflow = || invalid("number too large in patch");
+        let (mut data, mut shift) = (0usize, 1usize);
+
+        loop {
+            let x = self.take(1)?[0] as usize;
+            data = (x & 0x7f)
+                .checked_mul(shift)
+                .and_then(|n| n.checked_add(data))
+                .ok_or_else(overflow)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(1 << 7).ok_or_else(overflow)?;
+            data = data.checked_add(shift).ok_or_else(overflow)?;
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+}
+
+fn ips(old: &[u8], new: &[u8]) -> io::Result<Vec<u8>> {
+    if new.len() > IPS_LIMIT {
+        return Err(invalid("IPS patches can't address more than 16MiB"));
+    }
+
+    let mut patch = IPS_MAGIC.to_vec();
+    let mut i = 0;
+
+    while i < new.len() {
+        if old.get(i) == Some(&new[i]) {
+            i += 1;
+            continue;
+        }
+
+        let start = if i == IPS_EOF_OFFSET { i - 1 } else { i };
+        while i < new.len() && old.get(i) != Some(&new[i]) && i - start < 0xffff {
+            i += 1;
+        }
+
+        patch.extend(&(start as u32).to_be_bytes()[1..]);
+        patch.extend(((i - start) as u16).to_be_bytes());
+        patch.extend(&new[start..i]);
+    }
+
+    patch.extend(IPS_EOF);
+    if new.len() < old.len() {
+        patch.extend(&(new.len() as u32).to_be_bytes()[1..]);
+    }
+
+    Ok(patch)
+}
+
+fn ips_apply(patch: &[u8], source: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = Reader {
+        bytes: patch,
+        position: IPS_MAGIC.len(),
+    };
+    let mut data = source.to_vec();
+
+    loop {
+        if reader.take(3)? == IPS_EOF {
+            break;
+        }
+        reader.position -= 3;
+
+        let offset = reader.number(3)?;
+        let bytes = match reader.number(2)? {
+            0 => {
+                let count = reader.number(2)?;
+                vec![reader.take(1)?[0]; count]
+            }
+            size => reader.take(size)?.to_vec(),
+        };
+
+        let end = offset + bytes.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[offset..end].copy_from_slice(&bytes);
+    }
+
+    if reader.remaining() == 3 {
+        let length = reader.number(3)?;
+        data.truncate(length);
+    }
+
+    Ok(data)
+}
+
+fn varint(patch: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let x = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            patch.push(0x80 | x);
+            break;
+        }
+        patch.push(x);
+        value -= 1;
+    }
+}
+
+fn bps(old: &[u8], new: &[u8], hunks: &[Hunk]) -> Vec<u8> {
+    let mut patch = BPS_MAGIC.to_vec();
+    varint(&mut patch, old.len());
+    varint(&mut patch, new.len());
+    varint(&mut patch, 0);
+
+    let (mut i, mut output) = (0, 0);
+    let mut source_relative = 0;
+
+    let starts = hunks.iter().map(|hunk| hunk.new.start).chain([new.len()]);
+
+    for (n, new_start) in starts.enumerate() {
+        // Unchanged bytes before the hunk
+        let length = new_start - output;
+        if length > 0 {
+            if i == output {
+                varint(&mut patch, (length - 1) << 2);
+            } else {
+                varint(&mut patch, (length - 1) << 2 | 2);
+                let distance = i.abs_diff(source_relative);
+                varint(&mut patch, distance << 1 | usize::from(i < source_relative));
+                source_relative = i + length;
+            }
+        }
+
+        let Some(hunk) = hunks.get(n) else {
+            break;
+        };
+
+        if !hunk.new.is_empty() {
+            varint(&mut patch, (hunk.new.len() - 1) << 2 | 1);
+            patch.extend(&new[hunk.new.clone()]);
+        }
+        (i, output) = (hunk.old.end, hunk.new.end);
+    }
+
+    patch.extend(crc32fast::hash(old).to_le_bytes());
+    patch.extend(crc32fast::hash(new).to_le_bytes());
+    patch.extend(crc32fast::hash(&patch).to_le_bytes());
+
+    patch
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+/// Moves a relative offset of a BPS copy
+fn relative(base: usize, data: usize) -> io::Result<usize> {
+    let distance = data >> 1;
+    let offset = if data & 1 == 1 {
+        base.checked_sub(distance)
+    } else {
+        base.checked_add(distance)
+    };
+
+    offset.ok_or_else(|| invalid("copy out of bounds in patch"))
+}
+
+fn bps_apply(patch: &[u8], source: &[u8]) -> io::Result<Vec<u8>> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER {
+        return Err(truncated());
+    }
+
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER);
+    if crc32fast::hash(&patch[..patch.len() - 4]) != checksum(&footer[8..]) {
+        return Err(invalid("corrupted patch, its checksum doesn't match"));
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        position: BPS_MAGIC.len(),
+    };
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata = reader.varint()?;
+    reader.take(metadata)?;
+
+    if source.len() != source_size || crc32fast::hash(source) != checksum(&footer[..4]) {
+        return Err(invalid("the patch was made for a different file"));
+    }
+
+    let out_of_bounds = || invalid("copy out of bounds in patch");
+    let end = |start: usize, length| start.checked_add(length).ok_or_else(out_of_bounds);
+    // The size in the header isn't trusted until the checksums are checked
+    let capacity = std::cmp::min(target_size, source.len().saturating_add(patch.len()));
+    let mut target: Vec<u8> = Vec::with_capacity(capacity);
+    let (mut source_relative, mut target_relative) = (0, 0);
+
+    while reader.remaining() > 0 {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        if end(target.len(), length)? > target_size {
+            return Err(invalid(
+                "the patch writes past the size of the patched file",
+            ));
+        }
+
+        match data & 3 {
+            0 => {
+                let start = target.len();
+                let bytes = source
+                    .get(start..end(start, length)?)
+                    .ok_or_else(out_of_bounds)?;
+                target.extend(bytes);
+            }
+            1 => target.extend(reader.take(length)?),
+            2 => {
+                source_relative = relative(source_relative, reader.varint()?)?;
+                let bytes = source
+                    .get(source_relative..end(source_relative, length)?)
+                    .ok_or_else(out_of_bounds)?;
+                target.extend(bytes);
+                source_relative += length;
+            }
+            _ => {
+                target_relative = relative(target_relative, reader.varint()?)?;
+                // The copy can overlap the bytes it writes
+                for _ in 0..length {
+                    let byte = *target.get(target_relative).ok_or_else(out_of_bounds)?;
+                    target.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32fast::hash(&target) != checksum(&footer[4..8]) {
+        return Err(invalid(
+            "the patched file doesn't match the checksum of the patch",
+        ));
+    }
+
+    Ok(target)
+}
+
+fn bytes(text: &str) -> io::Result<Vec<u8>> {
+    text.split_whitespace()
+        .map(|byte| {
+            u8::from_str_radix(byte, 16).map_err(|_| invalid(format!("invalid byte {byte:?}")))
+        })
+        .collect()
+}
+
+/// A hunk of a hex patch: `expected` bytes at `offset` are replaced by `data`
+struct Record {
+    offset: usize,
+    length: usize,
+    expected: Vec<u8>,
+    data: Vec<u8>,
+}
+
+fn hex_apply(patch: &[u8], source: &[u8]) -> io::Result<Vec<u8>> {
+    let text = std::str::from_utf8(patch).map_err(|_| invalid("unknown patch format"))?;
+    let mut records: Vec<Record> = vec![];
+
+    for line in text.lines() {
+        if let Some(header) = line.strip_prefix("@@ ") {
+            let old = header.split(' ').next().unwrap_or_default();
+            let (offset, length) = old
+                .split_once(',')
+                .ok_or_else(|| invalid(format!("invalid hunk header {line:?}")))?;
+            records.push(Record {
+                offset: crate::number(offset).map_err(invalid)?,
+                length: crate::number(length).map_err(invalid)?,
+                expected: vec![],
+                data: vec![],
+            });
+        } else if let Some(record) = records.last_mut() {
+            if let Some(old) = line.strip_prefix("- ") {
+                record.expected = bytes(old)?;
+            } else if let Some(new) = line.strip_prefix("+ ") {
+                record.data = bytes(new)?;
+            }
+        }
+    }
+
+    // A patch without hunks still has the summary line
+    if records.is_empty() && !text.contains(" hunks, ") {
+        return Err(invalid("unknown patch format"));
+    }
+
+    let mut target = vec![];
+    let mut i = 0;
+
+    for record in records {
+        let end = record
+            .offset
+            .checked_add(record.length)
+            .ok_or_else(|| invalid(format!("invalid hunk at 0x{:08x}", record.offset)))?;
+        let original = source.get(record.offset..end);
+        if record.offset < i || original != Some(&record.expected[..]) {
+            return Err(invalid(format!(
+                "the patch doesn't match the file at 0x{:08x}",
+                record.offset
+            )));
+        }
+
+        target.extend(&source[i..record.offset]);
+        target.extend(record.data);
+        i = end;
+    }
+    target.extend(&source[i..]);
+
+    Ok(target)
+}
+
+/// Creates a patch turning `old` into `new`
+pub fn create(old: &[u8], new: &[u8], format: Format, algorithm: Algorithm) -> io::Result<Vec<u8>> {
+    match format {
+        Format::Ips => ips(old, new),
+        Format::Bps => Ok(bps(
+            old,
+            new,
+            &hunks(old, new, algorithm, &Progress::default()),
+        )),
+        Format::Hex => {
+            let hunks = hunks(old, new, algorithm, &Progress::default());
+            let lines = crate::diff::text(old, new, &hunks);
+            Ok(format!("{}\n", lines.join("\n")).into_bytes())
+        }
+    }
+}
+
+/// Applies a patch of any supported format to `source`
+pub fn apply(patch: &[u8], source: &[u8]) -> io::Result<Vec<u8>> {
+    match Format::detect(patch) {
+        Format::Ips => ips_apply(patch, source),
+        Format::Bps => bps_apply(patch, source),
+        Format::Hex => hex_apply(patch, source),
+    }
+}
+
+/// Edits of the data made by a patch, as ranges of `source` and their new bytes
+pub fn edits(patch: &[u8], source: &[u8]) -> io::Result<Vec<(Range<usize>, Vec<u8>)>> {
+    let target = apply(patch, source)?;
+    let hunks = hunks(source, &target, Algorithm::Myers, &Progress::default());
+
+    Ok(hunks
+        .into_iter()
+        .map(|hunk| (hunk.old, target[hunk.new].to_vec()))
+        .collect())
+}
+
+fn write(output: Option<&str>, bytes: &[u8]) -> io::Result<()> {
+    match output {
+        Some(path) => std::fs::write(path, bytes).map_err(crate::context(path)),
+        None => io::stdout().lock().write_all(bytes),
+    }
+}
+
+pub fn patch(command: Command) -> io::Result<()> {
+    match command {
+        Command::Create(options) => {
+            let old = crate::load(&options.old).map_err(crate::context(&options.old))?;
+            let new = crate::load(&options.new).map_err(crate::context(&options.new))?;
+            let patch = create(&old, &new, options.format, options.algorithm)
+                .map_err(crate::context(&options.new))?;
+            write(options.output.as_deref(), &patch)
+        }
+        Command::Apply(options) => {
+            let context = || crate::context(&options.patch);
+            let patch = std::fs::read(&options.patch).map_err(context())?;
+            let source = crate::load(&options.file).map_err(crate::context(&options.file))?;
+            let target = apply(&patch, &source).map_err(context())?;
+            write(options.output.as_deref(), &target)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &[u8] = b"the quick brown fox jumps over the lazy dog";
+    const NEW: &[u8] = b"the quick red fox jumped over the lazy dogs!";
+
+    fn round_trip(format: Format, old: &[u8], new: &[u8]) {
+        let patch = create(old, new, format, Algorithm::Myers).unwrap();
+        assert_eq!(Format::detect(&patch), format);
+        assert_eq!(apply(&patch, old).unwrap(), new, "{format:?}");
+    }
+
+    fn error(patch: &[u8], source: &[u8]) -> String {
+        let err = apply(patch, source).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn round_trips() {
+        for format in [Format::Ips, Format::Bps, Format::Hex] {
+            round_trip(format, OLD, NEW);
+            round_trip(format, NEW, OLD);
+            round_trip(format, OLD, OLD);
+            round_trip(format, b"", NEW);
+            round_trip(format, OLD, b"");
+        }
+    }
+
+    #[test]
+    fn ips_eof_offset() {
+        let old = vec![0; IPS_EOF_OFFSET + 2];
+        let mut new = old.clone();
+        new[IPS_EOF_OFFSET] = 1;
+        round_trip(Format::Ips, &old, &new);
+    }
+
+    #[test]
+    fn truncated_records() {
+        for format in [Format::Ips, Format::Bps] {
+            let patch = create(OLD, NEW, format, Algorithm::Myers).unwrap();
+            for length in [IPS_MAGIC.len() + 1, patch.len() / 2, patch.len() - 1] {
+                assert!(apply(&patch[..length], OLD).is_err(), "{format:?} {length}");
+            }
+        }
+
+        // A record of 4 bytes with only 2 of them
+        let patch = [IPS_MAGIC, &[0, 0, 1, 0, 4, 0xaa, 0xbb]].concat();
+        assert_eq!(error(&patch, OLD), "truncated patch");
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let mut patch = create(OLD, NEW, Format::Bps, Algorithm::Myers).unwrap();
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        assert_eq!(
+            error(&patch, OLD),
+            "corrupted patch, its checksum doesn't match"
+        );
+
+        let patch = create(OLD, NEW, Format::Bps, Algorithm::Myers).unwrap();
+        assert_eq!(
+            error(&patch, NEW),
+            "the patch was made for a different file"
+        );
+    }
+
+    /// BPS patch of `body` with valid checksums, for an empty source
+    fn bps_patch(body: &[u8]) -> Vec<u8> {
+        let mut patch = [BPS_MAGIC, body].concat();
+        patch.extend(crc32fast::hash(b"").to_le_bytes());
+        patch.extend(0u32.to_le_bytes());
+        patch.extend(crc32fast::hash(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn bps_overflow() {
+        // A size that doesn't fit in a usize
+        let patch = bps_patch(&[
+            0x80, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f,
+        ]);
+        assert_eq!(error(&patch, b""), "number too large in patch");
+
+        // Target of 1 byte, then a write of 2 bytes
+        let patch = bps_patch(&[0x80, 0x81, 0x80, 0x85, 1, 2]);
+        assert_eq!(
+            error(&patch, b""),
+            "the patch writes past the size of the patched file"
+        );
+
+        // Target of 1 byte, then a copy from before the start of the target
+        let patch = bps_patch(&[0x80, 0x81, 0x80, 0x83, 0x83]);
+        assert_eq!(error(&patch, b""), "copy out of bounds in patch");
+    }
+
+    #[test]
+    fn hex_overflow() {
+        let patch = format!("@@ 0x{:x},2 0x0,0 @@ deleted\n- 00 00\n", usize::MAX);
+        assert!(error(patch.as_bytes(), OLD).starts_with("invalid hunk at"));
+
+        let patch = "@@ 0x4,0xffff 0x4,0 @@ deleted\n- 00\n";
+        assert!(error(patch.as_bytes(), OLD).starts_with("the patch doesn't match"));
+
+        let patch = "@@ 0x0,1 0x0,1 @@ replaced\n- zz\n";
+        assert_eq!(error(patch.as_bytes(), OLD), "invalid byte \"zz\"");
+    }
+}
@@ -0,0 +1,369 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use arboard::Clipboard;
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Map, INT};
+
+use crate::{
+    config::Pair,
+    viewer::{Highlight, Mode, Viewer},
+};
+
+type Result<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+/// Copy of the viewer a script works on, offsets are in the data and not
+/// in the displayed positions
+struct State {
+    data: Vec<u8>,
+    selection: (usize, usize),
+    highlights: Vec<Highlight>,
+    colors: Vec<Pair>,
+    registers: HashMap<char, Vec<u8>>,
+    clipboard: Option<Clipboard>,
+    printed: Vec<String>,
+}
+
+impl State {
+    /// Offset given by a script, `end` allows the one past the last byte
+    fn offset(&self, offset: INT, end: bool) -> Result<usize> {
+        let length = self.data.len();
+        match usize::try_from(offset) {
+            Ok(offset) if offset < length || (end && offset == length) => Ok(offset),
+            _ if end => Err(format!("offset {offset} out of range 0..={length}").into()),
+            _ => Err(format!("offset {offset} out of range 0..{length}").into()),
+        }
+    }
+
+    /// Bytes from `offset` to the end of `length` bytes from there
+    fn range(&self, offset: INT, length: INT) -> Result<std::ops::Range<usize>> {
+        let start = self.offset(offset, true)?;
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| start.checked_add(length))
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| format!("{length} bytes at {start} out of range"))?;
+        Ok(start..end)
+    }
+
+    fn byte(value: INT) -> Result<u8> {
+        u8::try_from(value).map_err(|_| format!("{value} is not a byte").into())
+    }
+
+    /// Bytes given as a blob, an array of bytes or a string
+    fn bytes(value: Dynamic) -> Result<Vec<u8>> {
+        let kind = value.type_name();
+        if value.is_blob() {
+            return Ok(value.cast::<Blob>());
+        }
+        if let Some(array) = value.clone().try_cast::<Array>() {
+            return array
+                .into_iter()
+                .map(|v| {
+                    v.as_int()
+                        .map_err(|_| "arrays of bytes hold integers".into())
+                })
+                .map(|v| v.and_then(State::byte))
+                .collect();
+        }
+        match value.into_string() {
+            Ok(string) => Ok(string.into_bytes()),
+            Err(_) => Err(format!("expected bytes, got {kind}").into()),
+        }
+    }
+
+    fn register(name: &str) -> Result<char> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c == '"' || c.is_ascii_lowercase() => Ok(c),
+            _ => Err(format!("invalid register {name:?}").into()),
+        }
+    }
+
+    fn find(&self, pattern: &[u8], from: usize) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        self.data
+            .get(from..)?
+            .windows(pattern.len())
+            .position(|window| window == pattern)
+            .map(|index| from + index)
+    }
+}
+
+fn span(start: usize, end: usize) -> Map {
+    let mut map = Map::new();
+    map.insert("start".into(), (start as INT).into());
+    map.insert("end".into(), (end as INT).into());
+    map
+}
+
+/// Whether Esc or Ctrl-C was pressed, other keys are dropped
+fn interrupted() -> bool {
+    while event::poll(Duration::ZERO).unwrap_or(false) {
+        if let Ok(Event::Key(key)) = event::read() {
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.code == KeyCode::Esc || (ctrl && key.code == KeyCode::Char('c')) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Registers the functions scripts use to read and edit the buffer
+fn engine(state: &Rc<RefCell<State>>, interruptible: bool) -> Engine {
+    let mut engine = Engine::new();
+
+    // The terminal is raw while the viewer runs, so keys are read to stop
+    // scripts that run for too long
+    if interruptible {
+        engine.on_progress(|operations| {
+            (operations % 0x10000 == 0 && interrupted()).then(|| "interrupted".into())
+        });
+    }
+
+    let s = state.clone();
+    engine.on_print(move |text| s.borrow_mut().printed.push(text.to_string()));
+    let s = state.clone();
+    engine.on_debug(move |text, _, _| s.borrow_mut().printed.push(text.to_string()));
+
+    let s = state.clone();
+    engine.register_fn("len", move || s.borrow().data.len() as INT);
+
+    let s = state.clone();
+    engine.register_fn("read", move |offset: INT| -> Result<INT> {
+        let state = s.borrow();
+        Ok(state.data[state.offset(offset, false)?] as INT)
+    });
+    let s = state.clone();
+    engine.register_fn("read", move |offset: INT, length: INT| -> Result<Blob> {
+        let state = s.borrow();
+        Ok(state.data[state.range(offset, length)?].to_vec())
+    });
+
+    let s = state.clone();
+    engine.register_fn("write", move |offset: INT, value: INT| -> Result<()> {
+        let mut state = s.borrow_mut();
+        let offset = state.offset(offset, false)?;
+        state.data[offset] = State::byte(value)?;
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("write", move |offset: INT, bytes: Dynamic| -> Result<()> {
+        let bytes = State::bytes(bytes)?;
+        let mut state = s.borrow_mut();
+        let range = state.range(offset, bytes.len() as INT)?;
+        state.data[range].copy_from_slice(&bytes);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("insert", move |offset: INT, value: INT| -> Result<()> {
+        let mut state = s.borrow_mut();
+        let offset = state.offset(offset, true)?;
+        state.data.insert(offset, State::byte(value)?);
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("insert", move |offset: INT, bytes: Dynamic| -> Result<()> {
+        let bytes = State::bytes(bytes)?;
+        let mut state = s.borrow_mut();
+        let offset = state.offset(offset, true)?;
+        state.data.splice(offset..offset, bytes);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("delete", move |offset: INT, length: INT| -> Result<()> {
+        let mut state = s.borrow_mut();
+        let range = state.range(offset, length)?;
+        state.data.drain(range);
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("selection", move || {
+        let (start, end) = s.borrow().selection;
+        span(start, end)
+    });
+    let s = state.clone();
+    engine.register_fn("select", move |start: INT, end: INT| -> Result<()> {
+        let mut state = s.borrow_mut();
+        let (start, end) = (state.offset(start, false)?, state.offset(end, false)?);
+        state.selection = (std::cmp::min(start, end), std::cmp::max(start, end));
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("highlights", move || -> Array {
+        let state = s.borrow();
+        state
+            .highlights
+            .iter()
+            .map(|h| span(h.start, h.end).into())
+            .collect()
+    });
+    let s = state.clone();
+    engine.register_fn("highlight", move |start: INT, end: INT| -> Result<()> {
+        let mut state = s.borrow_mut();
+        let (start, end) = (state.offset(start, false)?, state.offset(end, false)?);
+        let Pair { bg, fg } = state.colors[state.highlights.len() % state.colors.len()];
+        state.highlights.push(Highlight {
+            start: std::cmp::min(start, end),
+            end: std::cmp::max(start, end),
+            bg,
+            fg,
+        });
+        Ok(())
+    });
+    let s = state.clone();
+    engine.register_fn("clear_highlights", move || {
+        s.borrow_mut().highlights.clear()
+    });
+
+    let s = state.clone();
+    engine.register_fn("find", move |pattern: Dynamic, from: INT| -> Result<INT> {
+        let pattern = State::bytes(pattern)?;
+        let state = s.borrow();
+        let from = state.offset(from, true)?;
+        Ok(state
+            .find(&pattern, from)
+            .map_or(-1, |offset| offset as INT))
+    });
+    let s = state.clone();
+    engine.register_fn("find_all", move |pattern: Dynamic| -> Result<Array> {
+        let pattern = State::bytes(pattern)?;
+        let state = s.borrow();
+        let mut found = vec![];
+        let mut from = 0;
+        while let Some(offset) = state.find(&pattern, from) {
+            found.push((offset as INT).into());
+            from = offset + 1;
+        }
+        Ok(found)
+    });
+
+    let s = state.clone();
+    engine.register_fn("register", move |name: &str| -> Result<Blob> {
+        let name = State::register(name)?;
+        Ok(s.borrow().registers.get(&name).cloned().unwrap_or_default())
+    });
+    let s = state.clone();
+    engine.register_fn(
+        "set_register",
+        move |name: &str, bytes: Dynamic| -> Result<()> {
+            let name = State::register(name)?;
+            s.borrow_mut().registers.insert(name, State::bytes(bytes)?);
+            Ok(())
+        },
+    );
+
+    let s = state.clone();
+    engine.register_fn("clipboard", move || -> Result<String> {
+        match &mut s.borrow_mut().clipboard {
+            Some(clipboard) => clipboard
+                .get_text()
+                .map_err(|err| format!("clipboard failed: {err}").into()),
+            None => Err("clipboard unavailable".into()),
+        }
+    });
+    let s = state.clone();
+    engine.register_fn("set_clipboard", move |text: &str| -> Result<()> {
+        match &mut s.borrow_mut().clipboard {
+            Some(clipboard) => clipboard
+                .set_text(text)
+                .map_err(|err| format!("clipboard failed: {err}").into()),
+            None => Err("clipboard unavailable".into()),
+        }
+    });
+
+    engine
+}
+
+/// What a script left once it ran
+pub struct Output {
+    pub printed: Vec<String>,
+    /// Whether it left no bytes, the viewer then holding a single zero unless
+    /// it started empty
+    pub empty: bool,
+}
+
+/// Runs the script at `path` on the viewer, its edits being a single step of
+/// the history, `interruptible` ones stop on Esc or Ctrl-C
+pub fn run(
+    viewer: &mut Viewer,
+    path: &str,
+    interruptible: bool,
+) -> std::result::Result<Output, String> {
+    let gaps = &viewer.gaps;
+    let highlights = viewer
+        .highlights
+        .iter()
+        .map(|h| Highlight {
+            start: gaps.index(h.start),
+            end: gaps.index(h.end),
+            ..*h
+        })
+        .collect();
+    let state = Rc::new(RefCell::new(State {
+        data: viewer.data.clone(),
+        selection: (
+            gaps.index(viewer.selection.start),
+            gaps.index(viewer.selection.end),
+        ),
+        highlights,
+        colors: viewer.config.theme.highlights.clone(),
+        registers: viewer.registers.clone(),
+        clipboard: viewer.clipboard.take(),
+        printed: vec![],
+    }));
+
+    let result = engine(&state, interruptible).run_file(path.into());
+
+    let state = Rc::try_unwrap(state).ok().unwrap().into_inner();
+    viewer.clipboard = state.clipboard;
+    result.map_err(|err| format!("{path}: {err}"))?;
+
+    // Only the bytes between the common start and end were edited
+    let (old, new) = (&viewer.data[..], &state.data[..]);
+    if old != new {
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let range = prefix..old.len() - suffix;
+        let inserted = new[prefix..new.len() - suffix].to_vec();
+        viewer.replace(vec![(range, inserted)]);
+    }
+
+    let gaps = &viewer.gaps;
+    let last = viewer.len().saturating_sub(1);
+    let position = |offset: usize| std::cmp::min(gaps.position(offset), last);
+    viewer.highlights = state
+        .highlights
+        .iter()
+        .map(|h| Highlight {
+            start: position(h.start),
+            end: position(h.end),
+            ..*h
+        })
+        .collect();
+    let (start, end) = state.selection;
+    viewer.selection.start = position(start);
+    viewer.selection.end = position(end);
+    viewer.mode = if start == end {
+        Mode::Normal
+    } else {
+        Mode::Visual
+    };
+    viewer.registers = state.registers;
+
+    Ok(Output {
+        printed: state.printed,
+        empty: state.data.is_empty(),
+    })
+}
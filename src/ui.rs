@@ -1,61 +1,174 @@
 use crate::{
-    comparator::Comparator,
-    viewer::{Mode, Viewer},
+    comparator::{Comparator, NEW, OLD},
+    format::Format,
+    merge::{count, Merge, BASE, OURS, THEIRS},
+    viewer::{Areas, Mode, Viewer},
 };
 mod hex;
 use hex::hex;
 
 mod index;
 use index::index;
+pub use index::offset;
 
 mod table;
+pub use table::printable;
 use table::table;
 
 mod info;
-use info::info;
+use info::{info, inspector};
 
 mod difference;
 use difference::difference;
 
+mod hunks;
+use hunks::{chunks, hunks};
+
+mod prompt;
+
+mod entropy;
+
 use ratatui::{layout::Constraint::*, prelude::*, widgets::*};
 
+/// What a mouse event points at in a viewer
+pub enum Target {
+    Byte(usize),
+    /// A row of the info panel, reading this many bytes at the cursor
+    Value(usize),
+    /// A block of the entropy panel, starting at this position
+    Block(usize),
+}
+
+pub fn target(viewer: &Viewer, column: u16, row: u16) -> Option<Target> {
+    let byte = hex::locate(viewer, column, row).or_else(|| table::locate(viewer, column, row));
+    match byte {
+        Some(position) => Some(Target::Byte(position)),
+        None => info::locate(viewer, column, row)
+            .map(Target::Value)
+            .or_else(|| entropy::locate(viewer, column, row).map(Target::Block)),
+    }
+}
+
+fn mode(viewer: &Viewer) -> &'static str {
+    match viewer.mode {
+        Mode::Normal => "NORMAL",
+        Mode::Insert => "INSERT",
+        Mode::Visual => "VISUAL",
+        Mode::Yank => "YANK",
+        Mode::Command { .. } => "COMMAND",
+        Mode::Checksum => "CHECKSUM",
+    }
+}
+
+/// Prompts drawn over the view for the focused viewer
+fn prompts<B: Backend>(f: &mut Frame<B>, viewer: &Viewer) {
+    if viewer.mode == Mode::Yank {
+        let area = prompt::area(24, Format::ALL.len() as u16 + 3, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(prompt::yank(), area);
+    }
+
+    if let (Mode::Checksum, Some(panel)) = (&viewer.mode, &viewer.checksums) {
+        let height = panel.values.len() as u16 + 2;
+        let area = prompt::area(prompt::checksums_width(panel), height, f.size());
+        f.render_widget(Clear, area);
+        f.render_widget(prompt::checksums(panel), area);
+    }
+}
+
 pub fn viewer_ui<B: Backend>(f: &mut Frame<B>, viewer: &mut Viewer) {
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(vec![Length(3), Min(0)])
         .split(f.size());
 
-    let mode = match viewer.mode {
-        Mode::Normal => "NORMAL",
-        Mode::Insert => "INSERT",
-        Mode::Visual => "VISUAL",
-    };
+    let mode = mode(viewer);
 
     let file = viewer.filename.unwrap_or("");
 
     let edited = if viewer.edited { "*" } else { "" };
-    let header = Paragraph::new(format!("  {mode}  |  {file}{edited}"))
+    let status = match &viewer.status {
+        Some(status) => format!("  |  {status}"),
+        None => String::new(),
+    };
+    let preview = match &viewer.preview {
+        Some(preview) => format!(
+            "  |  patch with {} changes, A to apply, Esc to discard",
+            preview.edits.len()
+        ),
+        None => String::new(),
+    };
+    let header = Paragraph::new(format!("  {mode}  |  {file}{edited}{preview}{status}"))
         .block(Block::default().title(" Lazyhex ").borders(Borders::ALL));
 
     f.render_widget(header, layout[0]);
 
+    let width = viewer.width as u16;
     let body = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints(vec![Length(82), Min(0)])
+        .constraints(vec![Length(width * 4 + 18), Min(0)])
         .split(layout[1]);
 
     let main = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints(vec![Length(11), Length(52), Length(19)])
+        .constraints(vec![Length(11), Length(width * 3 + 4), Length(width + 3)])
         .split(body[0]);
 
     let height = (body[0].height - 3) as usize;
+    viewer.follow(height);
 
-    let hextable = hex(viewer, height).block(
-        Block::default()
-            .padding(Padding::uniform(1))
-            .borders(Borders::RIGHT | Borders::LEFT),
-    );
+    let block = Block::default()
+        .padding(Padding::uniform(1))
+        .borders(Borders::RIGHT | Borders::LEFT);
+    let panels = &viewer.config.panels;
+    let side = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(match (panels.info, panels.entropy) {
+            (true, true) => vec![Length(19), Min(0)],
+            (true, false) => vec![Min(0), Length(0)],
+            (false, _) => vec![Length(0), Min(0)],
+        })
+        .split(body[1]);
+
+    let entropy_block = Block::default()
+        .title(" Entropy ")
+        .borders(Borders::ALL)
+        .padding(Padding::horizontal(1));
+    let entropy_area = entropy_block.inner(side[1]);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![
+            Length(1),
+            Ratio(1, 2),
+            Length(1),
+            Length(1),
+            Ratio(1, 2),
+        ])
+        .split(entropy_area);
+
+    if panels.entropy {
+        let range = viewer.selection_or_all();
+        let count = rows[1].width as usize;
+        viewer
+            .entropy
+            .update(viewer.data, viewer.version, count, range);
+    }
+
+    viewer.areas = Areas {
+        hex: block.inner(main[1]),
+        table: main[2],
+        info: if panels.info {
+            side[0]
+        } else {
+            Rect::default()
+        },
+        entropy: if panels.entropy {
+            rows[1]
+        } else {
+            Rect::default()
+        },
+    };
+    let hextable = hex(viewer, height).block(block);
 
     let index = index(viewer, height).block(Block::default().padding(Padding {
         left: 1,
@@ -71,7 +184,22 @@ pub fn viewer_ui<B: Backend>(f: &mut Frame<B>, viewer: &mut Viewer) {
     let block = Block::default().borders(Borders::ALL).title(" Hex ");
     f.render_widget(block, body[0]);
 
-    f.render_widget(info(viewer), body[1])
+    if panels.info {
+        f.render_widget(info(viewer), side[0]);
+    }
+
+    if panels.entropy {
+        let bins = entropy::bins(&viewer.entropy.histogram, rows[4].width as usize);
+        let (blocks, histogram) = entropy::labels(viewer, bins.len());
+        f.render_widget(entropy_block, side[1]);
+        f.render_widget(Paragraph::new(blocks), rows[0]);
+        f.render_widget(entropy::blocks(viewer), rows[1]);
+        f.render_widget(entropy::marker(viewer, rows[2].width as usize), rows[2]);
+        f.render_widget(Paragraph::new(histogram), rows[3]);
+        f.render_widget(entropy::histogram(&bins), rows[4]);
+    }
+
+    prompts(f, viewer);
 }
 
 pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator) {
@@ -80,15 +208,18 @@ pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator)
         .constraints(vec![Length(3), Min(0)])
         .split(f.size());
 
-    let file_old = comparator.viewer_old.filename.unwrap();
-    let file_new = comparator.viewer_new.filename.unwrap();
+    let [viewer_old, viewer_new] = &comparator.panes.viewers;
+    let file_old = viewer_old.filename.unwrap_or("-");
+    let file_new = viewer_new.filename.unwrap_or("-");
 
+    let config = comparator.config;
+    let theme = &config.theme;
     let mut header = vec![];
 
     if comparator.added > 0 {
         let added = Span::styled(
             format!("+{}", comparator.added),
-            Style::default().bg(Color::Green),
+            Style::default().bg(theme.added.bg).fg(theme.added.fg),
         );
         header.push(added);
         header.push(Span::raw(" "));
@@ -97,7 +228,7 @@ pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator)
     if comparator.deleted > 0 {
         let deleted = Span::styled(
             format!("-{}", comparator.deleted),
-            Style::default().bg(Color::Red),
+            Style::default().bg(theme.deleted.bg).fg(theme.deleted.fg),
         );
         header.push(deleted);
         header.push(Span::raw(" "));
@@ -106,29 +237,70 @@ pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator)
     if comparator.replaced > 0 {
         let replaced = Span::styled(
             format!("~{}", comparator.replaced),
-            Style::default().bg(Color::Yellow).fg(Color::Black),
+            Style::default().bg(theme.replaced.bg).fg(theme.replaced.fg),
         );
         header.push(replaced);
         header.push(Span::raw(" "));
     }
 
+    if !comparator.hunks.is_empty() {
+        let current = comparator.panes.hunk().map_or(0, |i| i + 1);
+        header.push(Span::raw(format!(
+            " hunk {current}/{}",
+            comparator.hunks.len()
+        )));
+    }
+
+    let focused = comparator.panes.focused();
+    let side = ["old", "new"][comparator.panes.focus];
+
     header.insert(
         0,
-        Span::from(format!(" Comparing {file_old:?} and {file_new:?}")),
+        Span::from(format!(
+            " {} {side}  |  Comparing {file_old:?} and {file_new:?}",
+            mode(focused)
+        )),
     );
     if header.len() > 1 {
         header.insert(1, Span::raw("  |  "));
     }
 
+    header.push(Span::raw(format!(
+        "  |  {}",
+        comparator.panes.algorithm.name()
+    )));
+
+    if let Some(progress) = comparator.panes.progress() {
+        header.push(Span::raw(format!(
+            "  |  computing diff {}.{}%",
+            progress / 10,
+            progress % 10
+        )));
+    }
+
+    if !comparator.panes.synced {
+        header.push(Span::raw("  |  unsynced"));
+    }
+
+    if let Some(status) = &focused.status {
+        header.push(Span::raw(format!("  |  {status}")));
+    }
+
     let header = Paragraph::new(Line::from(header))
         .block(Block::default().title(" Lazyhex ").borders(Borders::ALL));
 
     f.render_widget(header, layout[0]);
 
+    let panels = &config.panels;
+    let sides = [panels.difference, panels.inspector, panels.hunks];
+    // Width of a hex pane with its borders
+    let pane = config.width as u16 * 3 + 3;
     let width = layout[1].width;
-    let constraints = if width > 150 {
-        vec![Length(13), Length(width - 43), Length(30)]
-    } else if width > 115 {
+    let indexed = width > 2 * pane + 13;
+    let aside = width > 2 * pane + 64 && sides.contains(&true);
+    let constraints = if aside {
+        vec![Length(13), Length(width - 59), Length(46)]
+    } else if indexed {
         vec![Length(13), Length(width - 13)]
     } else {
         vec![Length(width)]
@@ -142,24 +314,41 @@ pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator)
     let comparing = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(vec![Ratio(1, 2), Ratio(1, 2)])
-        .split(body[if width > 115 { 1 } else { 0 }]);
+        .split(body[if indexed { 1 } else { 0 }]);
 
     let height = (body[0].height - 3) as usize;
+    for (viewer, area) in comparator.panes.viewers.iter_mut().zip(comparing.iter()) {
+        viewer.follow(height);
+        viewer.areas.hex = Block::default().borders(Borders::ALL).inner(*area);
+    }
 
-    let old = hex(&comparator.viewer_old, height).block(
+    let [viewer_old, viewer_new] = &comparator.panes.viewers;
+    let border = |side: usize| {
+        if comparator.panes.focus == side {
+            Style::default().fg(theme.focus)
+        } else {
+            Style::default()
+        }
+    };
+
+    let edited_old = if viewer_old.edited { "*" } else { "" };
+    let old = hex(viewer_old, height).block(
         Block::default()
-            .title(format!(" {file_old} "))
-            .borders(Borders::ALL),
+            .title(format!(" {file_old}{edited_old} "))
+            .borders(Borders::ALL)
+            .border_style(border(OLD)),
     );
 
-    let new = hex(&comparator.viewer_new, height).block(
+    let edited_new = if viewer_new.edited { "*" } else { "" };
+    let new = hex(viewer_new, height).block(
         Block::default()
-            .title(format!(" {file_new} "))
-            .borders(Borders::ALL),
+            .title(format!(" {file_new}{edited_new} "))
+            .borders(Borders::ALL)
+            .border_style(border(NEW)),
     );
 
-    if width > 115 {
-        let index = index(&comparator.viewer_new, height)
+    if indexed {
+        let index = index(comparator.panes.focused(), height)
             .alignment(Alignment::Center)
             .block(Block::default().borders(Borders::ALL).padding(Padding {
                 left: 0,
@@ -171,16 +360,226 @@ pub fn comparator_ui<B: Backend>(f: &mut Frame<B>, comparator: &mut Comparator)
         f.render_widget(index, body[0]);
     }
 
-    if width > 150 {
-        let diff = difference(&comparator).block(
+    if aside {
+        let constraints: Vec<_> = [Length(5), Length(18), Min(0)]
+            .into_iter()
+            .zip(sides)
+            .filter_map(|(constraint, shown)| shown.then_some(constraint))
+            .collect();
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(body[2]);
+        let mut side = side.iter();
+
+        if panels.difference {
+            let position = comparator.panes.focused().selection.start;
+            let diff = difference(viewer_old, viewer_new, position).block(
+                Block::default()
+                    .title(" Difference ")
+                    .borders(Borders::ALL)
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(diff, *side.next().unwrap());
+        }
+
+        if panels.inspector {
+            let inspector = inspector(comparator).block(
+                Block::default()
+                    .title(" Inspector ")
+                    .borders(Borders::ALL)
+                    .padding(Padding::horizontal(1)),
+            );
+            f.render_widget(inspector, *side.next().unwrap());
+        }
+
+        if panels.hunks {
+            let list = hunks(comparator).block(
+                Block::default()
+                    .title(" Hunks ")
+                    .borders(Borders::ALL)
+                    .padding(Padding::horizontal(1)),
+            );
+            let mut state = ListState::default().with_selected(comparator.panes.hunk());
+            f.render_stateful_widget(list, *side.next().unwrap(), &mut state);
+        }
+    }
+
+    f.render_widget(old, comparing[0]);
+    f.render_widget(new, comparing[1]);
+
+    prompts(f, comparator.panes.focused());
+}
+
+pub fn merge_ui<B: Backend>(f: &mut Frame<B>, merge: &mut Merge) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Length(3), Min(0)])
+        .split(f.size());
+
+    let config = merge.config;
+    let theme = &config.theme;
+    let focused = merge.panes.focused();
+    let pane = ["base", "ours", "theirs"][merge.panes.focus];
+    let [base, ours, theirs] = merge
+        .panes
+        .viewers
+        .each_ref()
+        .map(|v| v.filename.unwrap_or("-"));
+
+    let mut header = vec![Span::from(format!(
+        " {} {pane}  |  Base {base:?}, ours {ours:?}, theirs {theirs:?}  |  ",
+        mode(focused),
+    ))];
+
+    let (ours, theirs, both, conflicts) = count(&merge.chunks);
+    for (count, name, color) in [
+        (ours, "ours", theme.replaced.bg),
+        (theirs, "theirs", theme.replaced.bg),
+        (both, "both", theme.added.bg),
+        (conflicts, "conflicts", theme.conflict.bg),
+    ] {
+        if count > 0 {
+            header.push(Span::styled(
+                format!("{count} {name}"),
+                Style::default().fg(color),
+            ));
+            header.push(Span::raw(" "));
+        }
+    }
+
+    if !merge.chunks.is_empty() {
+        let current = merge.panes.hunk().map_or(0, |i| i + 1);
+        header.push(Span::raw(format!(
+            " chunk {current}/{}",
+            merge.chunks.len()
+        )));
+    }
+
+    header.push(Span::raw(format!("  |  {}", merge.panes.algorithm.name())));
+
+    if let Some(progress) = merge.panes.progress() {
+        header.push(Span::raw(format!(
+            "  |  computing diff {}.{}%",
+            progress / 10,
+            progress % 10
+        )));
+    }
+
+    if !merge.panes.synced {
+        header.push(Span::raw("  |  unsynced"));
+    }
+
+    if let Some(status) = &focused.status {
+        header.push(Span::raw(format!("  |  {status}")));
+    }
+
+    let header = Paragraph::new(Line::from(header))
+        .block(Block::default().title(" Lazyhex ").borders(Borders::ALL));
+
+    f.render_widget(header, layout[0]);
+
+    let panels = &config.panels;
+    let sides = [panels.difference, panels.difference, panels.hunks];
+    let pane = config.width as u16 * 3 + 3;
+    let width = layout[1].width;
+    let indexed = width > 3 * pane + 22;
+    let aside = width > 3 * pane + 57 && sides.contains(&true);
+    let constraints = if aside {
+        vec![Length(13), Length(width - 43), Length(30)]
+    } else if indexed {
+        vec![Length(13), Length(width - 13)]
+    } else {
+        vec![Length(width)]
+    };
+
+    let body = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(constraints)
+        .split(layout[1]);
+
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Ratio(1, 3), Ratio(1, 3), Ratio(1, 3)])
+        .split(body[if indexed { 1 } else { 0 }]);
+
+    let height = (body[0].height - 3) as usize;
+    for (viewer, area) in merge.panes.viewers.iter_mut().zip(panes.iter()) {
+        viewer.follow(height);
+        viewer.areas.hex = Block::default().borders(Borders::ALL).inner(*area);
+    }
+    let focused = merge.panes.focused();
+
+    for (i, viewer) in merge.panes.viewers.iter().enumerate() {
+        let edited = if viewer.edited { "*" } else { "" };
+        let border = if merge.panes.focus == i {
+            Style::default().fg(theme.focus)
+        } else {
+            Style::default()
+        };
+
+        let hextable = hex(viewer, height).block(
             Block::default()
-                .title(" Difference ")
+                .title(format!(" {}{edited} ", viewer.filename.unwrap_or("-")))
                 .borders(Borders::ALL)
-                .padding(Padding::horizontal(1)),
+                .border_style(border),
         );
-        f.render_widget(diff, body[2]);
+        f.render_widget(hextable, panes[i]);
     }
 
-    f.render_widget(old, comparing[0]);
-    f.render_widget(new, comparing[1]);
+    if indexed {
+        let index = index(focused, height).alignment(Alignment::Center).block(
+            Block::default().borders(Borders::ALL).padding(Padding {
+                left: 0,
+                right: 0,
+                top: 1,
+                bottom: 0,
+            }),
+        );
+
+        f.render_widget(index, body[0]);
+    }
+
+    if aside {
+        let constraints: Vec<_> = [Length(5), Length(5), Min(0)]
+            .into_iter()
+            .zip(sides)
+            .filter_map(|(constraint, shown)| shown.then_some(constraint))
+            .collect();
+        let side = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(constraints)
+            .split(body[2]);
+        let mut side = side.iter();
+
+        if panels.difference {
+            let position = focused.selection.start;
+            let viewers = &merge.panes.viewers;
+            for (title, viewer) in [
+                (" Base / Ours ", &viewers[OURS]),
+                (" Base / Theirs ", &viewers[THEIRS]),
+            ] {
+                let diff = difference(&viewers[BASE], viewer, position).block(
+                    Block::default()
+                        .title(title)
+                        .borders(Borders::ALL)
+                        .padding(Padding::horizontal(1)),
+                );
+                f.render_widget(diff, *side.next().unwrap());
+            }
+        }
+
+        if panels.hunks {
+            let list = chunks(merge).block(
+                Block::default()
+                    .title(" Chunks ")
+                    .borders(Borders::ALL)
+                    .padding(Padding::horizontal(1)),
+            );
+            let mut state = ListState::default().with_selected(merge.panes.hunk());
+            f.render_stateful_widget(list, *side.next().unwrap(), &mut state);
+        }
+    }
+
+    prompts(f, merge.panes.focused());
 }
@@ -4,9 +4,9 @@ use ratatui::{
     widgets::Paragraph,
 };
 
-use crate::comparator::Comparator;
+use crate::{config::Theme, viewer::Viewer};
 
-fn diff<'a>(a: u8, b: u8) -> Vec<Span<'a>> {
+fn diff<'a>(a: u8, b: u8, theme: &Theme) -> Vec<Span<'a>> {
     let d = a ^ b;
 
     (0..8)
@@ -14,9 +14,9 @@ fn diff<'a>(a: u8, b: u8) -> Vec<Span<'a>> {
         .map(|i| {
             if (d >> i) & 0b1 == 1 {
                 if (b >> i) & 0b1 == 1 {
-                    Span::from("1".green())
+                    "1".fg(theme.added.bg)
                 } else {
-                    Span::from("0".red())
+                    "0".fg(theme.deleted.bg)
                 }
             } else {
                 Span::raw(" ")
@@ -25,37 +25,38 @@ fn diff<'a>(a: u8, b: u8) -> Vec<Span<'a>> {
         .collect()
 }
 
-pub fn difference<'a>(comparator: &Comparator) -> Paragraph<'a> {
-    let position = comparator.viewer_old.selection.start;
-    let o = &comparator.viewer_old.data[position];
-    let n = &comparator.viewer_new.data[position];
+/// Bits that differ between two aligned buffers at `position`
+pub fn difference<'a>(old: &Viewer, new: &Viewer, position: usize) -> Paragraph<'a> {
+    let theme = &old.config.theme;
+    let o = old.get(position);
+    let n = new.get(position);
 
-    let spans = match (*o, *n) {
-        (None, None) => unreachable!(),
+    let spans = match (o, n) {
+        (None, None) => vec![vec![Span::raw("")]; 3],
         (None, Some(new)) => {
             vec![
                 vec![Span::raw("")],
-                vec![Span::from(" ++++++ ".green())],
+                vec![" ++++++ ".fg(theme.added.bg)],
                 vec![Span::from(format!("{new:08b}"))],
             ]
         }
         (Some(old), None) => {
             vec![
                 vec![Span::from(format!("{old:08b}"))],
-                vec![Span::from(" ------ ".red())],
+                vec![" ------ ".fg(theme.deleted.bg)],
                 vec![Span::raw("")],
             ]
         }
         (Some(old), Some(new)) => {
             vec![
                 vec![Span::from(format!("{old:08b}"))],
-                diff(old, new),
+                diff(old, new, theme),
                 vec![Span::from(format!("{new:08b}"))],
             ]
         }
     };
 
-    let lines: Vec<_> = spans.into_iter().map(|s| Line::from(s)).collect();
+    let lines: Vec<_> = spans.into_iter().map(Line::from).collect();
 
     Paragraph::new(lines)
 }
@@ -0,0 +1,74 @@
+use ratatui::{
+    text::Line,
+    widgets::{Paragraph, Sparkline},
+};
+
+use crate::viewer::Viewer;
+
+/// Position of the start of the block drawn at `column` and `row` of the terminal
+pub fn locate(viewer: &Viewer, column: u16, row: u16) -> Option<usize> {
+    let area = viewer.areas.entropy;
+    if column < area.x || row < area.y || row >= area.y + area.height {
+        return None;
+    }
+
+    let entropy = &viewer.entropy;
+    let block = (column - area.x) as usize;
+    if block >= entropy.blocks.len() {
+        return None;
+    }
+
+    let position = viewer.gaps.position(block * entropy.size);
+    Some(std::cmp::min(position, viewer.len() - 1))
+}
+
+/// Entropy of each block, 8 bits per byte being the top
+pub fn blocks<'a>(viewer: &'a Viewer) -> Sparkline<'a> {
+    Sparkline::default().data(&viewer.entropy.blocks).max(800)
+}
+
+/// Marks the block under the cursor, with its offset on the side with more room
+pub fn marker<'a>(viewer: &Viewer, width: usize) -> Paragraph<'a> {
+    let entropy = &viewer.entropy;
+    let index = viewer.gaps.index(viewer.selection.end);
+    let block = index / entropy.size.max(1);
+    let offset = format!("0x{:X}", block * entropy.size);
+
+    let line = if block < width / 2 {
+        format!("{}^ {offset}", " ".repeat(block))
+    } else {
+        let padding = block.saturating_sub(offset.len() + 1);
+        format!("{}{offset} ^", " ".repeat(padding))
+    };
+
+    Paragraph::new(line)
+}
+
+/// Counts of the histogram added up in `width` bars at most, none until it
+/// is first computed
+pub fn bins(histogram: &[u64], width: usize) -> Vec<u64> {
+    let size = histogram.len().div_ceil(width.max(1)).max(1);
+    histogram.chunks(size).map(|bin| bin.iter().sum()).collect()
+}
+
+pub fn histogram(bins: &[u64]) -> Sparkline<'_> {
+    Sparkline::default().data(bins)
+}
+
+pub fn labels<'a>(viewer: &Viewer, bins: usize) -> (Line<'a>, Line<'a>) {
+    let entropy = &viewer.entropy;
+    let blocks = Line::from(format!(
+        "{} blocks of {} bytes, mean {:.2} bits",
+        entropy.blocks.len(),
+        entropy.size,
+        entropy.mean()
+    ));
+    let histogram = Line::from(format!(
+        "bytes of 0x{:X}..0x{:X}, {} values a bar",
+        entropy.range.start,
+        entropy.range.end,
+        256_usize.div_ceil(bins.max(1))
+    ));
+
+    (blocks, histogram)
+}
@@ -7,29 +7,49 @@ use ratatui::{
 
 use crate::viewer::{Highlight, Viewer};
 
-fn convert(x: usize) -> (usize, usize) {
-    let col = x / 16;
-    let row = x % 16;
-    let row = row * 2 + if row < 8 { 0 } else { 1 };
+fn convert(x: usize, width: usize) -> (usize, usize) {
+    let col = x / width;
+    let row = x % width;
+    let row = row * 2 + if row < width.div_ceil(2) { 0 } else { 1 };
 
     (col, row)
 }
 
+/// Position of the byte drawn at `column` and `row` of the terminal, the
+/// reverse of `convert` once the line is centered in the pane
+pub fn locate(viewer: &Viewer, column: u16, row: u16) -> Option<usize> {
+    let area = viewer.areas.hex;
+    let width = viewer.width;
+
+    // Lines are as wide as the header, cut to the pane
+    let line = std::cmp::min(width as u16 * 3 + 1, area.width);
+    let left = area.x + (area.width / 2).saturating_sub(line / 2);
+    if column < left || column >= left + line || row <= area.y || row >= area.y + area.height {
+        return None;
+    }
+
+    let x = (column - left) as usize;
+    let half = width.div_ceil(2);
+    let col = if x < half * 3 { x / 3 } else { (x - 1) / 3 };
+    let position = (viewer.top + (row - area.y - 1) as usize) * width + col.min(width - 1);
+
+    Some(std::cmp::min(position, viewer.len() - 1))
+}
+
 pub fn hex<'a>(viewer: &Viewer, height: usize) -> Paragraph<'a> {
-    let mut spans: Vec<_> = viewer
-        .data
-        .chunks(16)
-        .map(|chunk| {
-            chunk.iter().map(|n| match n {
-                Some(x) => Span::from(format!("{x:02x}")),
+    let width = viewer.width;
+    let bytes = &viewer.config.theme.bytes;
+    let rows = viewer.top..std::cmp::min(viewer.top + height, viewer.len().div_ceil(width));
+    let (first, last) = (rows.start * width, rows.end * width);
+
+    let mut spans: Vec<_> = rows
+        .map(|row| {
+            let chunk = (row * width..row * width + width).map(|i| match viewer.get(i) {
+                Some(x) => Span::styled(format!("{x:02x}"), Style::default().fg(bytes.color(x))),
                 None => Span::raw("  "),
-            })
-        })
-        .map(|chunk| {
-            let len = chunk.len();
-            let chunk = chunk.chain((len..16).map(|_| Span::raw("  ")));
+            });
             let mut chunk: Vec<_> = chunk.flat_map(|span| [span, Span::raw(" ")]).collect();
-            chunk.insert(15, Span::raw(" "));
+            chunk.insert(width.div_ceil(2) * 2 - 1, Span::raw(" "));
 
             chunk
         })
@@ -39,12 +59,19 @@ pub fn hex<'a>(viewer: &Viewer, height: usize) -> Paragraph<'a> {
     let highlights = viewer.highlights.iter().chain(selection.iter());
 
     for Highlight { start, end, bg, fg } in highlights {
-        for (i, selected) in (*start..=*end).enumerate() {
-            let (col, row) = convert(selected);
+        if *end < first || *start >= last {
+            continue;
+        }
+
+        let start = std::cmp::max(*start, first);
+        let end = std::cmp::min(*end, last - 1);
+
+        for selected in start..=end {
+            let (col, row) = convert(selected - first, width);
             spans[col][row].patch_style(Style::default().bg(*bg).fg(*fg));
 
-            if i != 0 {
-                let (colp, rowp) = convert(selected - 1);
+            if selected != start {
+                let (colp, rowp) = convert(selected - first - 1, width);
 
                 if col == colp && row - rowp == 2 {
                     spans[col][row - 1].patch_style(Style::default().bg(*bg).fg(*fg));
@@ -53,22 +80,13 @@ pub fn hex<'a>(viewer: &Viewer, height: usize) -> Paragraph<'a> {
         }
     }
 
-    let spans = spans.into_iter().map(|chunk| Line::from(chunk));
+    let spans = spans.into_iter().map(Line::from);
 
-    let mut header: Vec<_> = (0..16).map(|i| Span::from(format!(" {i:x} "))).collect();
-    header.insert(8, Span::raw(" "));
+    let mut header: Vec<_> = (0..width).map(|i| Span::from(format!("{i:^3x}"))).collect();
+    header.insert(width.div_ceil(2), Span::raw(" "));
     let header = Line::from(header);
 
-    let skip = if viewer.selection.end / 16 > height - 1 {
-        viewer.selection.end / 16 + 1 - height
-    } else {
-        0
-    };
-
-    let spans: Vec<_> = [header]
-        .into_iter()
-        .chain(spans.skip(skip).take(height))
-        .collect();
+    let spans: Vec<_> = [header].into_iter().chain(spans).collect();
 
     Paragraph::new(spans).alignment(Alignment::Center)
 }
@@ -0,0 +1,56 @@
+use ratatui::{
+    style::Style,
+    widgets::{List, ListItem},
+};
+
+use crate::{
+    comparator::{Comparator, Kind},
+    merge::{Merge, BASE, OURS},
+};
+
+/// Lists the hunks at their offsets in the old and the new file
+pub fn hunks<'a>(comparator: &Comparator) -> List<'a> {
+    let theme = &comparator.config.theme;
+    let items: Vec<_> = comparator
+        .hunks
+        .iter()
+        .map(|hunk| {
+            let (symbol, size, color) = match hunk.kind {
+                Kind::Added => ('+', hunk.new.len().to_string(), theme.added.bg),
+                Kind::Deleted => ('-', hunk.old.len().to_string(), theme.deleted.bg),
+                Kind::Replaced => (
+                    '~',
+                    format!("{}/{}", hunk.old.len(), hunk.new.len()),
+                    theme.replaced.bg,
+                ),
+            };
+
+            let (old, new) = (hunk.old.start, hunk.new.start);
+            ListItem::new(format!("{symbol} 0x{old:06X} / 0x{new:06X}  {size}"))
+                .style(Style::default().fg(color))
+        })
+        .collect();
+
+    List::new(items).highlight_style(Style::default().bg(theme.selection.bg))
+}
+
+/// Lists the chunks at their offsets in the file of the focused pane
+pub fn chunks<'a>(merge: &Merge) -> List<'a> {
+    let theme = &merge.config.theme;
+    let items: Vec<_> = merge
+        .chunks
+        .iter()
+        .map(|chunk| {
+            let color = chunk.change.color(theme).bg;
+            let offset = match merge.panes.focus {
+                BASE => chunk.base.start,
+                OURS => chunk.ours.start,
+                _ => chunk.theirs.start,
+            };
+            ListItem::new(format!("0x{offset:06X}  {}", chunk.change.name()))
+                .style(Style::default().fg(color))
+        })
+        .collect();
+
+    List::new(items).highlight_style(Style::default().bg(theme.selection.bg))
+}
@@ -2,24 +2,24 @@ use ratatui::{prelude::Alignment, style::Style, text::Line, widgets::Paragraph};
 
 use crate::viewer::Viewer;
 
+pub fn offset(offset: usize) -> String {
+    format!("0x{offset:06X}")
+}
+
 pub fn index<'a>(viewer: &Viewer, height: usize) -> Paragraph<'a> {
-    let skip = if viewer.selection.end / 16 > height - 1 {
-        viewer.selection.end / 16 + 1 - height
-    } else {
-        0
-    };
+    let width = viewer.width;
+
+    let rows = viewer.len().div_ceil(width);
 
-    let indexes: Vec<_> = (0..viewer.data.chunks(16).len())
+    let indexes: Vec<_> = (viewer.top..std::cmp::min(viewer.top + height, rows))
         .map(|i| {
-            let id = format!("0x{i:05X}0");
-            if i >= viewer.selection.start / 16 && i <= viewer.selection.end / 16 {
-                Line::styled(id, Style::default().bg(ratatui::style::Color::DarkGray))
+            let id = offset(i * width);
+            if i >= viewer.selection.start / width && i <= viewer.selection.end / width {
+                Line::styled(id, Style::default().bg(viewer.selection.bg))
             } else {
                 Line::from(id)
             }
         })
-        .skip(skip)
-        .take(height)
         .collect();
 
     Paragraph::new(indexes).alignment(Alignment::Right)
@@ -1,61 +1,86 @@
-use ratatui::widgets::{Block, Borders, List, ListItem, Padding, Widget};
+use ratatui::{
+    layout::Constraint::*,
+    style::{Style, Stylize},
+    widgets::{Block, Borders, List, ListItem, Padding, Row, Table, Widget},
+};
 
-use crate::viewer::Viewer;
+use crate::{comparator::Comparator, config::Endian, viewer::Viewer};
 
-fn slice(data: &[Option<u8>], offset: usize, length: usize) -> Vec<u8> {
+fn slice(data: &[u8], offset: usize, length: usize) -> Vec<u8> {
     let mut v = vec![0; length];
-    let mut offset = offset;
-
-    let mut i = 0;
-    while i < length {
-        if let Some(x) = data.get(i + offset) {
-            if let Some(y) = x {
-                v[i] = *y;
-                i += 1;
-            } else {
-                offset += 1;
-            }
-        } else {
-            v[i] = 0;
-            i += 1;
-        }
-    }
+    let available = data.get(offset..).unwrap_or_default();
+    let length = std::cmp::min(length, available.len());
+    v[..length].copy_from_slice(&available[..length]);
 
     v
 }
 
-pub fn info(viewer: &Viewer) -> impl Widget {
-    let byte = slice(viewer.data, viewer.selection.start, 1)[0];
+/// Interpretations of the bytes at the cursor with the number of bytes they
+/// read, empty on a gap
+fn values(viewer: &Viewer) -> Vec<(&'static str, Option<usize>, String)> {
+    let offset = viewer.gaps.index(viewer.selection.start);
+    let byte = slice(viewer.data, offset, 1)[0];
 
-    let long = slice(viewer.data, viewer.selection.start, 8)
-        .into_iter()
-        .fold(0, |acc, x| (acc << 8) | u64::from(x));
+    let bytes = slice(viewer.data, offset, 8).try_into().unwrap();
+    // Smaller integers are read from the first bytes
+    let (long, short, int) = match viewer.endian {
+        Endian::Big => {
+            let long = u64::from_be_bytes(bytes);
+            (long, (long >> 48) as u16, (long >> 32) as u32)
+        }
+        Endian::Little => {
+            let long = u64::from_le_bytes(bytes);
+            (long, long as u16, long as u32)
+        }
+    };
 
-    let string = slice(
-        viewer.data,
-        viewer.selection.start,
-        viewer.selection.end - viewer.selection.start + 1,
-    );
+    let string = viewer.selected();
     let string: String = string.iter().map(|c| *c as char).collect();
 
-    let items = [
-        ListItem::new(format!("hex:     0x{:02x}", byte)),
-        ListItem::new(format!("binary:  0b{:b}", byte)),
-        ListItem::new(format!("octal:   0o{:o}", byte)),
-        ListItem::new(format!("i8:      {}", byte as i8)),
-        ListItem::new(format!("u8:      {}", byte as u8)),
-        ListItem::new(format!("i16:     {}", (long >> 48) as i16)),
-        ListItem::new(format!("u16:     {}", (long >> 48) as u16)),
-        ListItem::new(format!("i32:     {}", (long >> 32) as i32)),
-        ListItem::new(format!("u32:     {}", (long >> 32) as u32)),
-        ListItem::new(format!("i64:     {}", long as i64)),
-        ListItem::new(format!("u64:     {}", long as u64)),
-        ListItem::new(format!("f32:     {:.5e}", (long >> 32) as f32)),
-        ListItem::new(format!("f64:     {:.5e}", long as f64)),
-        ListItem::new(format!("char:    {}", byte as char)),
-        ListItem::new(format!("string: {:?}", string)),
+    let values = [
+        ("hex", Some(1), format!("0x{:02x}", byte)),
+        ("binary", Some(1), format!("0b{:b}", byte)),
+        ("octal", Some(1), format!("0o{:o}", byte)),
+        ("i8", Some(1), format!("{}", byte as i8)),
+        ("u8", Some(1), format!("{}", byte)),
+        ("i16", Some(2), format!("{}", short as i16)),
+        ("u16", Some(2), format!("{}", short)),
+        ("i32", Some(4), format!("{}", int as i32)),
+        ("u32", Some(4), format!("{}", int)),
+        ("i64", Some(8), format!("{}", long as i64)),
+        ("u64", Some(8), format!("{}", long)),
+        ("f32", Some(4), format!("{:.5e}", f32::from_bits(int))),
+        ("f64", Some(8), format!("{:.5e}", f64::from_bits(long))),
+        ("char", Some(1), format!("{}", byte as char)),
+        ("string", None, format!("{:?}", string)),
     ];
 
+    let gap = viewer.get(viewer.selection.start).is_none();
+    values
+        .into_iter()
+        .map(|(name, size, value)| (name, size, if gap { String::new() } else { value }))
+        .collect()
+}
+
+/// Number of bytes read by the row of the info panel drawn at `column` and `row`
+/// of the terminal
+pub fn locate(viewer: &Viewer, column: u16, row: u16) -> Option<usize> {
+    let area = viewer.areas.info;
+    // Inside the borders and the padding of the block
+    let (left, top) = (area.x + 2, area.y + 2);
+    if column < left || column + 2 >= area.x + area.width || row < top {
+        return None;
+    }
+
+    values(viewer).get((row - top) as usize)?.1
+}
+
+pub fn info(viewer: &Viewer) -> impl Widget {
+    let items: Vec<_> = values(viewer)
+        .into_iter()
+        .map(|(name, _, value)| ListItem::new(format!("{:<8} {value}", format!("{name}:"))))
+        .collect();
+
     List::new(items).block(
         Block::default()
             .title(" Info ")
@@ -63,3 +88,28 @@ pub fn info(viewer: &Viewer) -> impl Widget {
             .padding(Padding::uniform(1)),
     )
 }
+
+/// Interpretations of the bytes at the cursor in both panes, differing rows highlighted
+pub fn inspector<'a>(comparator: &Comparator) -> Table<'a> {
+    let [old, new] = comparator.panes.viewers.each_ref().map(values);
+
+    let rows: Vec<_> = old
+        .into_iter()
+        .zip(new)
+        .map(|((name, _, old), (_, _, new))| {
+            let style = if old != new {
+                let replaced = comparator.config.theme.replaced;
+                Style::default().bg(replaced.bg).fg(replaced.fg)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![name.to_string(), old, new]).style(style)
+        })
+        .collect();
+
+    let header = Row::new(vec!["", "old", "new"]).style(Style::default().bold());
+
+    Table::new(rows)
+        .header(header)
+        .widths(&[Length(7), Ratio(1, 2), Ratio(1, 2)])
+}
@@ -0,0 +1,81 @@
+use ratatui::{
+    layout::{Constraint::*, Direction, Layout, Rect},
+    style::Stylize,
+    text::{Line, Span},
+    widgets::{Block, Borders, Padding, Paragraph},
+};
+
+use crate::{
+    checksum::{hex, Panel},
+    format::Format,
+};
+
+pub fn area(width: u16, height: u16, r: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Min(0), Length(height), Min(0)])
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Min(0), Length(width), Min(0)])
+        .split(vertical[1])[1]
+}
+
+pub fn yank<'a>() -> Paragraph<'a> {
+    let register = Line::from(vec![Span::from("y").bold(), Span::raw("  register only")]);
+
+    let lines: Vec<_> = [register]
+        .into_iter()
+        .chain(Format::ALL.iter().map(|f| {
+            Line::from(vec![
+                Span::from(f.key().to_string()).bold(),
+                Span::raw(format!("  {}", f.name())),
+            ])
+        }))
+        .collect();
+
+    Paragraph::new(lines).block(
+        Block::default()
+            .title(" Yank as ")
+            .borders(Borders::ALL)
+            .padding(Padding::horizontal(1)),
+    )
+}
+
+/// Width of the checksums prompt, fitting the longest value
+pub fn checksums_width(panel: &Panel) -> u16 {
+    let longest = panel
+        .values
+        .iter()
+        .map(|(_, v)| v.len() * 2)
+        .max()
+        .unwrap_or(0);
+    (longest + 18) as u16
+}
+
+pub fn checksums<'a>(panel: &Panel) -> Paragraph<'a> {
+    let lines: Vec<_> = panel
+        .values
+        .iter()
+        .enumerate()
+        .map(|(i, (checksum, value))| {
+            let span = Span::from(format!("{:<13} {}", checksum.name(), hex(value)));
+            if i == panel.selected {
+                Line::from(span.reversed())
+            } else {
+                Line::from(span)
+            }
+        })
+        .collect();
+
+    Paragraph::new(lines).block(
+        Block::default()
+            .title(format!(
+                " Checksums of {} bytes, y to yank ",
+                panel.range.len()
+            ))
+            .borders(Borders::ALL)
+            .padding(Padding::horizontal(1)),
+    )
+}
@@ -1,44 +1,61 @@
 use ratatui::{
     prelude::Alignment,
-    style::{Color, Style},
+    style::Style,
     text::{Line, Span},
     widgets::{Block, Padding, Paragraph, Widget},
 };
 
 use crate::viewer::Viewer;
 
-pub fn table(viewer: &Viewer, height: usize) -> impl Widget {
-    let skip = if viewer.selection.end / 16 > height - 1 {
-        viewer.selection.end / 16 + 1 - height
+pub fn printable(byte: u8) -> char {
+    if byte > 32 && byte < 127 {
+        byte as char
     } else {
-        0
-    };
-
-    let table: Vec<_> = viewer
-        .data
-        .chunks(16)
-        .enumerate()
-        .map(|(i, chunk)| {
-            (0..16)
-                .map(|i| match chunk.get(i) {
-                    Some(&Some(c)) if c > 32 && c < 127 => c as char,
-                    Some(None) => ' ',
-                    Some(_) => '.',
-                    None => ' ',
-                })
-                .enumerate()
-                .map(|(j, c)| {
-                    if i * 16 + j >= viewer.selection.start && i * 16 + j <= viewer.selection.end {
-                        Span::styled(c.to_string(), Style::default().bg(Color::DarkGray))
-                    } else {
-                        Span::raw(c.to_string())
+        '.'
+    }
+}
+
+/// Position of the character drawn at `column` and `row` of the terminal
+pub fn locate(viewer: &Viewer, column: u16, row: u16) -> Option<usize> {
+    let area = viewer.areas.table;
+    // Inside the padding of the block
+    let (left, top) = (area.x + 1, area.y + 2);
+    let right = std::cmp::min(left + viewer.width as u16, area.x + area.width);
+    if column < left || column >= right || row < top || row >= area.y + area.height {
+        return None;
+    }
+
+    let position = (viewer.top + (row - top) as usize) * viewer.width + (column - left) as usize;
+
+    Some(std::cmp::min(position, viewer.len() - 1))
+}
+
+pub fn table(viewer: &Viewer, height: usize) -> impl Widget {
+    let width = viewer.width;
+    let bytes = &viewer.config.theme.bytes;
+
+    let rows = viewer.len().div_ceil(width);
+
+    let table: Vec<_> = (viewer.top..std::cmp::min(viewer.top + height, rows))
+        .map(|i| {
+            (0..width)
+                .map(|j| {
+                    let position = i * width + j;
+                    let mut span = match viewer.get(position) {
+                        Some(c) => Span::styled(
+                            printable(c).to_string(),
+                            Style::default().fg(bytes.color(c)),
+                        ),
+                        None => Span::raw(" "),
+                    };
+                    if position >= viewer.selection.start && position <= viewer.selection.end {
+                        span.patch_style(Style::default().bg(viewer.selection.bg));
                     }
+                    span
                 })
                 .collect::<Vec<_>>()
         })
-        .map(|s| Line::from(s))
-        .skip(skip)
-        .take(height)
+        .map(Line::from)
         .collect();
 
     Paragraph::new(table)
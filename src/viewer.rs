@@ -1,14 +1,31 @@
+use std::{collections::HashMap, ops::Range};
+
 use arboard::Clipboard;
-use ratatui::style::Color;
+use ratatui::{layout::Rect, style::Color};
+
+use crate::{
+    checksum::Panel,
+    config::{Config, Endian, Pair},
+    entropy::Entropy,
+    format::Format,
+};
 
 #[derive(PartialEq)]
 pub enum Mode {
     Normal,
     Insert,
     Visual,
+    Yank,
+    /// Typing a command after `:`, which works on the selection when opened
+    /// from visual mode
+    Command {
+        visual: bool,
+    },
+    /// Looking at the checksums panel
+    Checksum,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Highlight {
     pub start: usize,
     pub end: usize,
@@ -23,118 +40,392 @@ impl Highlight {
     }
 }
 
+/// Empty positions displayed to align the buffer with another one
+#[derive(Default)]
+pub struct Gaps {
+    /// Displayed positions of the gaps with the length of the gaps before them
+    ranges: Vec<(Range<usize>, usize)>,
+    pub total: usize,
+}
+
+impl Gaps {
+    /// Gaps have to be pushed in order
+    pub fn push(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let length = range.len();
+        match self.ranges.last_mut() {
+            Some((last, _)) if last.end == range.start => last.end = range.end,
+            _ => self.ranges.push((range, self.total)),
+        }
+        self.total += length;
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.total = 0;
+    }
+
+    pub fn contains(&self, position: usize) -> bool {
+        let i = self.ranges.partition_point(|(r, _)| r.end <= position);
+        self.ranges.get(i).is_some_and(|(r, _)| r.start <= position)
+    }
+
+    /// Number of bytes of data displayed before `position`
+    pub fn index(&self, position: usize) -> usize {
+        let i = self.ranges.partition_point(|(r, _)| r.start < position);
+        match i.checked_sub(1).map(|i| &self.ranges[i]) {
+            Some((r, before)) => position - before - (std::cmp::min(position, r.end) - r.start),
+            None => position,
+        }
+    }
+
+    /// Displayed position of the byte at `index` in the data
+    pub fn position(&self, index: usize) -> usize {
+        let i = self
+            .ranges
+            .partition_point(|(r, before)| r.start - before <= index);
+        match i.checked_sub(1).map(|i| &self.ranges[i]) {
+            Some((r, before)) => index + before + r.len(),
+            None => index,
+        }
+    }
+}
+
+/// Bytes removed and inserted at `index` by an edit, enough to undo or redo it
+struct Change {
+    index: usize,
+    removed: Vec<u8>,
+    inserted: Vec<u8>,
+}
+
+/// Changes of a patch shown before being applied
+pub struct Preview {
+    /// Ranges of the data replaced by the patch, in order
+    pub edits: Vec<(Range<usize>, Vec<u8>)>,
+    highlights: Vec<Highlight>,
+}
+
+/// Where the panes of a viewer were last drawn, to map mouse events back to bytes
+#[derive(Default, Clone, Copy)]
+pub struct Areas {
+    /// Inside the borders of the hex pane
+    pub hex: Rect,
+    pub table: Rect,
+    pub info: Rect,
+    /// Entropy of the blocks, one column each
+    pub entropy: Rect,
+}
+
 pub struct Viewer<'a> {
-    pub data: &'a mut Vec<Option<u8>>,
+    pub data: &'a mut Vec<u8>,
+    pub gaps: Gaps,
     pub selection: Highlight,
     pub filename: Option<&'a str>,
+    pub config: &'a Config,
+    /// Bytes per row
+    pub width: usize,
+    pub endian: Endian,
     pub mode: Mode,
     pub highlights: Vec<Highlight>,
     pub edited: bool,
-    clipboard: Clipboard,
+    /// Incremented on every change of the data
+    pub version: usize,
+    pub status: Option<String>,
+    pub registers: HashMap<char, Vec<u8>>,
+    pub register: char,
+    pub preview: Option<Preview>,
+    pub checksums: Option<Panel>,
+    pub entropy: Entropy,
+    /// First row shown
+    pub top: usize,
+    /// Number of rows shown
+    pub height: usize,
+    pub areas: Areas,
+    /// Cursor position when the view last followed it
+    cursor: usize,
+    pub clipboard: Option<Clipboard>,
+    /// Steps of the history, each one made of changes in the order they were applied
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
 }
 
-const COLORS: [(Color, Color); 4] = [
-    (Color::Red, Color::White),
-    (Color::Green, Color::White),
-    (Color::Yellow, Color::Black),
-    (Color::Blue, Color::White),
-];
+pub const UNNAMED: char = '"';
+
+/// Bytes pasted at most by a paste with a count
+const PASTE_LIMIT: usize = 1 << 24;
 
 impl<'a> Viewer<'a> {
-    pub fn new(data: &'a mut Vec<Option<u8>>, filename: Option<&'a str>) -> Self {
+    pub fn new(data: &'a mut Vec<u8>, filename: Option<&'a str>, config: &'a Config) -> Self {
         Self {
             selection: Highlight {
                 start: 0,
                 end: 0,
-                bg: Color::DarkGray,
-                fg: Color::White,
+                bg: config.theme.selection.bg,
+                fg: config.theme.selection.fg,
             },
             data,
+            gaps: Gaps::default(),
             filename,
+            config,
+            width: config.width,
+            endian: config.endian,
             mode: Mode::Normal,
             highlights: vec![],
             edited: false,
-            clipboard: Clipboard::new().unwrap(),
+            version: 0,
+            status: None,
+            registers: HashMap::new(),
+            register: UNNAMED,
+            preview: None,
+            checksums: None,
+            entropy: Entropy::default(),
+            top: 0,
+            height: 1,
+            areas: Areas::default(),
+            cursor: 0,
+            clipboard: Clipboard::new().ok(),
+            undo: vec![],
+            redo: vec![],
         }
     }
 
-    pub fn left(&mut self) {
-        if self.selection.end > 0 {
-            self.selection.end -= 1;
+    /// Number of displayed positions, including the gaps
+    pub fn len(&self) -> usize {
+        self.data.len() + self.gaps.total
+    }
+
+    pub fn get(&self, position: usize) -> Option<u8> {
+        if position >= self.len() || self.gaps.contains(position) {
+            None
+        } else {
+            Some(self.data[self.gaps.index(position)])
         }
+    }
+
+    /// Range of the data covered by the selection
+    fn range(&self) -> Range<usize> {
+        self.gaps.index(self.selection.start)..self.gaps.index(self.selection.end + 1)
+    }
+
+    /// Moves the cursor, or the end of the selection in visual mode
+    pub fn go(&mut self, position: usize) {
+        self.selection.end = std::cmp::min(position, self.len() - 1);
 
-        if self.mode == Mode::Normal {
+        if self.mode != Mode::Visual {
             self.selection.set(self.selection.end);
-        } else if self.mode == Mode::Visual && self.selection.end < self.selection.start {
+        } else if self.selection.end < self.selection.start {
             self.selection.set(self.selection.start);
         }
     }
 
+    pub fn left(&mut self) {
+        self.go(self.selection.end.saturating_sub(1));
+    }
+
     pub fn right(&mut self) {
-        if self.selection.end < self.data.len() - 1 {
-            self.selection.end += 1;
+        self.go(self.selection.end + 1);
+    }
+
+    /// Whether a run of zero bytes starts at `position`
+    fn zeros(&self, position: usize) -> bool {
+        self.get(position) == Some(0) && (position == 0 || self.get(position - 1) != Some(0))
+    }
+
+    /// Start of the `count`th run of zero bytes after `position`, or the end
+    pub fn zeros_after(&self, position: usize, count: usize) -> usize {
+        (position + 1..self.len())
+            .filter(|p| self.zeros(*p))
+            .nth(count - 1)
+            .unwrap_or(self.len())
+    }
+
+    /// Start of the `count`th run of zero bytes before `position`, or the start
+    pub fn zeros_before(&self, position: usize, count: usize) -> usize {
+        (0..position)
+            .rev()
+            .filter(|p| self.zeros(*p))
+            .nth(count - 1)
+            .unwrap_or(0)
+    }
+
+    /// Rows kept between the cursor and the edges of the view, at most half of it
+    fn scrolloff(&self) -> usize {
+        std::cmp::min(self.config.scrolloff, (self.height - 1) / 2)
+    }
+
+    /// Scrolls to the cursor if it moved since the last call, with `height` rows shown
+    pub fn follow(&mut self, height: usize) {
+        self.height = std::cmp::max(height, 1);
+
+        if self.selection.end != self.cursor {
+            self.cursor = self.selection.end;
+            let row = self.cursor / self.width;
+            let margin = self.scrolloff();
+            if row < self.top + margin {
+                self.top = row.saturating_sub(margin);
+            } else if row + margin >= self.top + self.height {
+                self.top = row + margin + 1 - self.height;
+            }
         }
 
-        if self.mode != Mode::Visual {
-            self.selection.set(self.selection.end);
+        self.scroll(0);
+    }
+
+    /// Moves the view by `rows`, leaving the cursor where it is
+    pub fn scroll(&mut self, rows: isize) {
+        let last = self.len().div_ceil(self.width).saturating_sub(self.height);
+        self.top = std::cmp::min(self.top.saturating_add_signed(rows), last);
+    }
+
+    /// Moves the view and the cursor by `rows`
+    pub fn page(&mut self, rows: isize) {
+        self.scroll(rows);
+        let bytes = rows.saturating_mul(self.width as isize);
+        self.go(self.selection.end.saturating_add_signed(bytes));
+    }
+
+    /// Rows moved by half a page and by a whole one
+    pub fn pages(&self) -> (isize, isize) {
+        let height = self.height as isize;
+        (std::cmp::max(height / 2, 1), std::cmp::max(height - 2, 1))
+    }
+
+    /// Scrolls so that the cursor is `row` rows below the top of the view
+    fn place(&mut self, row: usize) {
+        self.top = (self.selection.end / self.width).saturating_sub(row);
+        self.scroll(0);
+    }
+
+    pub fn scroll_center(&mut self) {
+        self.place(self.height / 2);
+    }
+
+    pub fn scroll_top(&mut self) {
+        self.place(self.scrolloff());
+    }
+
+    pub fn scroll_bottom(&mut self) {
+        self.place(self.height - 1 - self.scrolloff());
+    }
+
+    pub fn modified(&mut self) {
+        self.edited = true;
+        self.version += 1;
+        // The ranges of a patch being previewed don't match the data anymore
+        if self.preview.is_some() {
+            self.discard();
+            self.status = Some("patch preview discarded by the edit".to_string());
         }
     }
 
-    pub fn up(&mut self) {
-        if self.selection.end < 16 {
-            self.selection.end = 0;
-        } else {
-            self.selection.end -= 16;
+    /// Replaces ranges of the data, given in order, as one step of the history
+    pub fn replace(&mut self, edits: Vec<(Range<usize>, Vec<u8>)>) {
+        let mut step = vec![];
+
+        // From the end so that the ranges before stay valid
+        for (range, inserted) in edits.into_iter().rev() {
+            let removed = self.data.splice(range.clone(), inserted.clone()).collect();
+            step.push(Change {
+                index: range.start,
+                removed,
+                inserted,
+            });
         }
 
-        if self.mode != Mode::Visual {
-            self.selection.set(self.selection.end);
-        } else {
-            self.selection.end = std::cmp::max(self.selection.start, self.selection.end);
+        if self.data.is_empty() {
+            self.data.push(0);
+            step.push(Change {
+                index: 0,
+                removed: vec![],
+                inserted: vec![0],
+            });
         }
+
+        self.undo.push(step);
+        self.redo.clear();
+        self.modified();
     }
 
-    pub fn down(&mut self) {
-        self.selection.end = std::cmp::min(self.data.len() - 1, self.selection.end + 16);
+    /// Moves the cursor to the first byte touched by a step of the history
+    fn restore(&mut self, step: &[Change]) {
+        let index = step.iter().map(|c| c.index).min().unwrap_or(0);
+        let position = std::cmp::min(self.gaps.position(index), self.len() - 1);
+        self.mode = Mode::Normal;
+        self.selection.set(position);
+    }
 
-        if self.mode != Mode::Visual {
-            self.selection.set(self.selection.end);
+    /// Undoes a step of the history, returns false when there is none
+    pub fn undo(&mut self) -> bool {
+        let Some(step) = self.undo.pop() else {
+            self.status = Some("already at the oldest change".to_string());
+            return false;
+        };
+
+        for change in step.iter().rev() {
+            let range = change.index..change.index + change.inserted.len();
+            self.data.splice(range, change.removed.iter().copied());
         }
+
+        self.modified();
+        self.restore(&step);
+        self.redo.push(step);
+        true
     }
 
-    pub fn set(&mut self, value: Option<u8>) {
-        self.edited = true;
-        for selected in self.selection.start..=self.selection.end {
-            self.data[selected] = value;
+    /// Redoes a step of the history, returns false when there is none
+    pub fn redo(&mut self) -> bool {
+        let Some(step) = self.redo.pop() else {
+            self.status = Some("already at the newest change".to_string());
+            return false;
+        };
+
+        for change in step.iter() {
+            let range = change.index..change.index + change.removed.len();
+            self.data.splice(range, change.inserted.iter().copied());
         }
+
+        self.modified();
+        self.restore(&step);
+        self.undo.push(step);
+        true
+    }
+
+    pub fn set(&mut self, value: u8) {
+        let range = self.range();
+        let bytes = vec![value; range.len()];
+        self.replace(vec![(range, bytes)]);
+    }
+
+    /// Rewrites the selected bytes as one step of the history
+    pub fn transform(&mut self, f: impl FnOnce(&mut [u8])) {
+        let range = self.range();
+        let mut bytes = self.data[range.clone()].to_vec();
+        f(&mut bytes);
+        self.replace(vec![(range, bytes)]);
     }
 
     pub fn flush(&mut self) {
         if let Some(path) = &self.filename {
-            let data: Vec<u8> = self
-                .data
-                .iter()
-                .filter(|d| d.is_some())
-                .map(|d| d.unwrap())
-                .collect();
-
-            let _ = std::fs::write(path, data);
+            let _ = std::fs::write(path, &self.data);
             self.edited = false;
+        } else {
+            self.status = Some("nothing to save to, the data came from stdin".to_string());
         }
     }
 
     pub fn append(&mut self) {
-        self.edited = true;
-        if self.selection.end + 1 < self.data.len() {
-            self.data.insert(self.selection.end + 1, Some(0));
-        } else {
-            self.data.push(Some(0));
-        }
+        let index = self.gaps.index(self.selection.end + 1);
+        self.replace(vec![(index..index, vec![0])]);
     }
 
     pub fn delete(&mut self) {
-        self.edited = true;
-        self.data.drain(self.selection.start..=self.selection.end);
+        let bytes = self.selected();
+        self.store(bytes);
+        let range = self.range();
+        self.replace(vec![(range, vec![])]);
 
         let length = self.selection.end - self.selection.start + 1;
 
@@ -151,11 +442,7 @@ impl<'a> Viewer<'a> {
             }
         }
 
-        if self.data.is_empty() {
-            self.data.push(Some(0));
-        }
-
-        self.selection.end = std::cmp::min(self.selection.end, self.data.len() - 1);
+        self.selection.end = std::cmp::min(self.selection.end, self.len() - 1);
     }
 
     pub fn highlight(&mut self) {
@@ -167,7 +454,8 @@ impl<'a> Viewer<'a> {
         if let Some(index) = prev {
             self.highlights.remove(index);
         } else {
-            let (bg, fg) = COLORS[self.highlights.len() % COLORS.len()];
+            let colors = &self.config.theme.highlights;
+            let Pair { bg, fg } = colors[self.highlights.len() % colors.len()];
 
             self.highlights.push(Highlight {
                 bg,
@@ -177,17 +465,203 @@ impl<'a> Viewer<'a> {
         }
     }
 
-    pub fn yank(&mut self) {
-        println!("adsf");
-        let text: String = self.data[self.selection.start..=self.selection.end]
+    /// Range of the data covered by the selection in visual mode, everything
+    /// otherwise
+    pub fn selection_or_all(&self) -> Range<usize> {
+        match self.mode {
+            Mode::Visual | Mode::Command { visual: true } => self.range(),
+            _ => 0..self.data.len(),
+        }
+    }
+
+    pub fn selected(&self) -> Vec<u8> {
+        self.data[self.range()].to_vec()
+    }
+
+    pub fn select_register(&mut self, name: char) -> bool {
+        if name == UNNAMED || name.is_ascii_alphabetic() {
+            self.register = name;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn store(&mut self, bytes: Vec<u8>) {
+        let name = std::mem::replace(&mut self.register, UNNAMED);
+
+        if name.is_ascii_uppercase() {
+            let register = self.registers.entry(name.to_ascii_lowercase()).or_default();
+            register.extend(bytes);
+            let bytes = register.clone();
+            self.registers.insert(UNNAMED, bytes);
+        } else {
+            if name != UNNAMED {
+                self.registers.insert(name, bytes.clone());
+            }
+            self.registers.insert(UNNAMED, bytes);
+        }
+    }
+
+    pub fn yank(&mut self, format: Option<Format>) {
+        let bytes = self.selected();
+        self.yank_bytes(bytes, format);
+    }
+
+    /// Stores bytes in the register, and in the clipboard in a format
+    pub fn yank_bytes(&mut self, bytes: Vec<u8>, format: Option<Format>) {
+        let length = bytes.len();
+        let register = self.register;
+        self.store(bytes.clone());
+
+        let Some(format) = format else {
+            self.status = Some(format!("yanked {length} bytes into \"{register}"));
+            return;
+        };
+
+        self.status = match &mut self.clipboard {
+            Some(clipboard) => match clipboard.set_text(format.format(&bytes)) {
+                Ok(()) => Some(format!("yanked {length} bytes as {}", format.name())),
+                Err(err) => Some(format!("yanked {length} bytes, clipboard failed: {err}")),
+            },
+            None => Some(format!(
+                "yanked {length} bytes into \"{register}, clipboard unavailable"
+            )),
+        };
+    }
+
+    /// Pastes the register `count` times as one step of the history
+    pub fn paste(&mut self, after: bool, count: usize) {
+        let name = std::mem::replace(&mut self.register, UNNAMED);
+        let Some(bytes) = self.registers.get(&name.to_ascii_lowercase()) else {
+            self.status = Some(format!("register \"{name} is empty"));
+            return;
+        };
+
+        if bytes.is_empty() {
+            return;
+        }
+
+        // Counts past the limit paste no more copies
+        let count = count.clamp(1, std::cmp::max(PASTE_LIMIT / bytes.len(), 1));
+        let bytes = bytes.repeat(count);
+
+        let length = bytes.len();
+        let position = if after {
+            self.selection.end + 1
+        } else {
+            self.selection.start
+        };
+
+        let index = self.gaps.index(position);
+        self.replace(vec![(index..index, bytes)]);
+
+        for highlight in self.highlights.iter_mut() {
+            if highlight.start >= position {
+                highlight.start += length;
+            }
+
+            if highlight.end >= position {
+                highlight.end += length;
+            }
+        }
+
+        self.selection.set(position + length - 1);
+    }
+
+    /// Shows the edits of a patch as highlights until they are applied or discarded
+    pub fn preview(&mut self, edits: Vec<(Range<usize>, Vec<u8>)>) {
+        self.discard();
+
+        let Pair { bg, fg } = self.config.theme.preview;
+        let highlights: Vec<_> = edits
             .iter()
-            .filter(|d| d.is_some())
-            .map(|d| d.unwrap())
-            .map(|d| d as char)
+            .map(|(range, _)| {
+                let start = self.gaps.position(range.start);
+                // Insertions are shown on the byte they are inserted before
+                let end = std::cmp::max(start, self.gaps.position(range.end).saturating_sub(1));
+                Highlight {
+                    start: std::cmp::min(start, self.len() - 1),
+                    end: std::cmp::min(end, self.len() - 1),
+                    bg,
+                    fg,
+                }
+            })
             .collect();
 
-        self.clipboard.set_text(text).unwrap();
+        self.highlights.extend(highlights.iter().copied());
+        if let Some(first) = highlights.first() {
+            self.selection.set(first.start);
+        }
+        self.preview = Some(Preview { edits, highlights });
+    }
+
+    /// Applies the patch being previewed as a single step of the history
+    pub fn apply(&mut self) {
+        let Some(preview) = self.preview.take() else {
+            self.status = Some("no patch to apply".to_string());
+            return;
+        };
+
+        self.highlights.retain(|h| !preview.highlights.contains(h));
+        let count = preview.edits.len();
+        self.replace(preview.edits);
+        self.selection.end = std::cmp::min(self.selection.end, self.len() - 1);
+        self.selection.set(self.selection.end);
+        self.status = Some(format!("applied {count} changes"));
+    }
+
+    pub fn discard(&mut self) {
+        if let Some(preview) = self.preview.take() {
+            self.highlights.retain(|h| !preview.highlights.contains(h));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes 0 and 1, two gaps, bytes 2 and 3, one gap, then the other bytes
+    fn gaps() -> Gaps {
+        let mut gaps = Gaps::default();
+        gaps.push(2..3);
+        gaps.push(3..4);
+        gaps.push(5..5);
+        gaps.push(6..7);
+        gaps
+    }
+
+    #[test]
+    fn gaps_merge() {
+        let gaps = gaps();
+        assert_eq!(gaps.ranges, [(2..4, 0), (6..7, 2)]);
+        assert_eq!(gaps.total, 3);
+    }
+
+    #[test]
+    fn gaps_contain() {
+        let gaps = gaps();
+        let contained: Vec<_> = (0..10).filter(|p| gaps.contains(*p)).collect();
+        assert_eq!(contained, [2, 3, 6]);
+    }
+
+    #[test]
+    fn gaps_index() {
+        let gaps = gaps();
+        let indexes: Vec<_> = (0..10).map(|p| gaps.index(p)).collect();
+        // Positions on a gap count the bytes before it
+        assert_eq!(indexes, [0, 1, 2, 2, 2, 3, 4, 4, 5, 6]);
+    }
+
+    #[test]
+    fn gaps_position() {
+        let gaps = gaps();
+        let positions: Vec<_> = (0..7).map(|i| gaps.position(i)).collect();
+        assert_eq!(positions, [0, 1, 4, 5, 7, 8, 9]);
+        assert!((0..7).all(|i| gaps.index(gaps.position(i)) == i));
 
-        println!("{}", self.clipboard.get_text().unwrap());
+        let empty = Gaps::default();
+        assert_eq!((empty.index(5), empty.position(5)), (5, 5));
     }
 }